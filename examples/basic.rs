@@ -12,13 +12,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Replace the drive_id with a Shared Drive ID your service account has access to.
     match bernard.sync_drive("0A1xxxxxxxxxUk9PVA").await? {
         // Do not do anything on a full-sync.
-        SyncKind::Full => (),
+        SyncKind::Full(_) => (),
 
         // Print the changes this partial sync fetched.
         SyncKind::Partial(changes) => {
             let paths = changes.paths().await?;
             println!("changed paths: {:#?}", paths);
         }
+
+        // Print the paths which were removed with the Shared Drive.
+        SyncKind::Removed(changes) => {
+            let paths = changes.paths().await?;
+            println!("removed paths: {:#?}", paths);
+        }
     }
 
     // Close Bernard's internal connection pool.
@@ -59,13 +59,19 @@ async fn main() -> Result<()> {
     // Sync the provided Shared Drive.
     match bernard.sync_drive(&opt.drive_id).await? {
         // Do not do anything on a full-sync.
-        SyncKind::Full => (),
+        SyncKind::Full(_) => (),
 
         // Print the changes this partial sync fetched.
         SyncKind::Partial(changes) => {
             let paths = changes.paths().await?;
             println!("changed paths: {:#?}", paths);
         }
+
+        // Print the paths which were removed with the Shared Drive.
+        SyncKind::Removed(changes) => {
+            let paths = changes.paths().await?;
+            println!("removed paths: {:#?}", paths);
+        }
     }
 
     // Close Bernard's internal connection pool.
@@ -0,0 +1,131 @@
+use serde_json::{json, Value};
+
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+pub const DOCUMENT_MIME_TYPE: &str = "application/vnd.google-apps.document";
+pub const SHORTCUT_MIME_TYPE: &str = "application/vnd.google-apps.shortcut";
+pub const BINARY_MIME_TYPE: &str = "application/octet-stream";
+
+/// A folder, file, document or shortcut within a fake Shared Drive or My Drive.
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub id: String,
+    pub name: String,
+    pub mime_type: String,
+    pub parents: Vec<String>,
+    pub md5: Option<String>,
+    pub size: Option<u64>,
+    pub trashed: bool,
+    /// The ID and MIME type of the target of a shortcut.
+    pub shortcut_target: Option<(String, String)>,
+}
+
+impl Item {
+    fn new(id: &str, name: &str, parent: &str, mime_type: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            mime_type: mime_type.to_owned(),
+            parents: vec![parent.to_owned()],
+            md5: None,
+            size: None,
+            trashed: false,
+            shortcut_target: None,
+        }
+    }
+
+    pub fn folder(id: &str, name: &str, parent: &str) -> Self {
+        Self::new(id, name, parent, FOLDER_MIME_TYPE)
+    }
+
+    /// A binary file with a checksum and size derived from its ID.
+    pub fn file(id: &str, name: &str, parent: &str) -> Self {
+        Self::new(id, name, parent, BINARY_MIME_TYPE)
+            .with_md5(&format!("md5-{}", id))
+            .with_size(id.len() as u64)
+    }
+
+    pub fn document(id: &str, name: &str, parent: &str) -> Self {
+        Self::new(id, name, parent, DOCUMENT_MIME_TYPE)
+    }
+
+    pub fn shortcut(
+        id: &str,
+        name: &str,
+        parent: &str,
+        target_id: &str,
+        target_mime_type: &str,
+    ) -> Self {
+        let mut item = Self::new(id, name, parent, SHORTCUT_MIME_TYPE);
+        item.shortcut_target = Some((target_id.to_owned(), target_mime_type.to_owned()));
+        item
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    pub fn with_parents(mut self, parents: &[&str]) -> Self {
+        self.parents = parents.iter().map(|parent| parent.to_string()).collect();
+        self
+    }
+
+    pub fn with_mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_type = mime_type.to_owned();
+        self
+    }
+
+    pub fn with_md5(mut self, md5: &str) -> Self {
+        self.md5 = Some(md5.to_owned());
+        self
+    }
+
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_trashed(mut self, trashed: bool) -> Self {
+        self.trashed = trashed;
+        self
+    }
+
+    /// The file resource as returned by the Google Drive API.
+    /// Items outside of a Shared Drive do not have a drive ID.
+    pub(crate) fn to_json(&self, drive_id: Option<&str>) -> Value {
+        let mut value = json!({
+            "id": self.id,
+            "name": self.name,
+            "mimeType": self.mime_type,
+            "parents": self.parents,
+            "trashed": self.trashed,
+        });
+
+        if let Some(drive_id) = drive_id {
+            value["driveId"] = json!(drive_id);
+        }
+
+        // The API omits the parents of items shared with the user from outside of My Drive.
+        if self.parents.is_empty() {
+            value.as_object_mut().unwrap().remove("parents");
+        }
+
+        if let Some(md5) = &self.md5 {
+            value["md5Checksum"] = json!(md5);
+        }
+
+        // The API returns the size as a string.
+        if let Some(size) = self.size {
+            value["size"] = json!(size.to_string());
+        }
+
+        if let Some((target_id, target_mime_type)) = &self.shortcut_target {
+            value["shortcutDetails"] = json!({
+                "targetId": target_id,
+                "targetMimeType": target_mime_type,
+            });
+        }
+
+        value
+    }
+}
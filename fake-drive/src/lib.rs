@@ -0,0 +1,570 @@
+//! An in-process fake of the Google Drive API for testing Bernard without network access.
+//!
+//! The fake emulates `files.list`, `files.get` of the My Drive root, `changes.list`,
+//! `changes.getStartPageToken`, `drives.get`, `drives.list` and the OAuth 2.0 token endpoint
+//! on top of a programmable in-memory tree. Every modification of the tree is appended to the change log.
+
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use state::{Change, Drive, Failure, State};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::oneshot;
+
+mod item;
+mod state;
+
+pub use item::{Item, BINARY_MIME_TYPE, DOCUMENT_MIME_TYPE, FOLDER_MIME_TYPE, SHORTCUT_MIME_TYPE};
+
+/// A Service Account key which is accepted by the fake.
+pub const SERVICE_ACCOUNT: &str = include_str!("../service-account.json");
+
+/// The prefix of the access tokens handed out by the token endpoint.
+/// Every bearer token starting with this token is accepted, unless it has been revoked.
+pub const ACCESS_TOKEN: &str = "fake-access-token";
+
+/// The default page size of the list endpoints.
+const PAGE_SIZE: usize = 100;
+
+/// A fake Google Drive API listening on a random local port.
+/// The server is shut down when the fake is dropped.
+pub struct FakeDrive {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FakeDrive {
+    /// Start the server on the current Tokio runtime.
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let state = state.clone();
+
+                    async move {
+                        let (parts, body) = request.into_parts();
+                        let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+
+                        // The token endpoint is not part of the Drive API.
+                        let api = parts.uri.path() != "/token";
+                        if api {
+                            let latency = start_request(&state);
+                            if let Some(latency) = latency {
+                                tokio::time::sleep(latency).await;
+                            }
+                        }
+
+                        let response = handle(&state, Request::from_parts(parts, body));
+
+                        if api {
+                            state.lock().unwrap().in_flight -= 1;
+                        }
+
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+
+        let (shutdown, signal) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            signal.await.ok();
+        }));
+
+        Self {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    /// The base URL of the Drive API, see `BernardBuilder::api_base_url`.
+    pub fn api_base_url(&self) -> String {
+        format!("http://{}/drive/v3", self.addr)
+    }
+
+    /// The URL of the token endpoint, see `BernardBuilder::token_url`.
+    pub fn token_url(&self) -> String {
+        format!("http://{}/token", self.addr)
+    }
+
+    /// Add an empty Shared Drive, whose root folder has the same ID as the drive.
+    pub fn add_drive(&self, drive_id: &str, name: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.drives.insert(
+            drive_id.to_owned(),
+            Drive {
+                name: name.to_owned(),
+                items: BTreeMap::new(),
+                my_drive: false,
+            },
+        );
+    }
+
+    /// Add the empty My Drive of the user, whose root folder has the provided ID.
+    /// Items are added with [`FakeDrive::upsert`] using the root folder ID as drive ID.
+    pub fn add_my_drive(&self, root_id: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.drives.insert(
+            root_id.to_owned(),
+            Drive {
+                name: "My Drive".to_owned(),
+                items: BTreeMap::new(),
+                my_drive: true,
+            },
+        );
+    }
+
+    /// Rename the Shared Drive, which is recorded as a drive change.
+    pub fn rename_drive(&self, drive_id: &str, name: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.drive_mut(drive_id).name = name.to_owned();
+        state.changes.push(Change::DriveChanged {
+            drive_id: drive_id.to_owned(),
+            name: name.to_owned(),
+        });
+    }
+
+    /// Delete the Shared Drive, after which all its endpoints respond with `404 Not Found`.
+    pub fn remove_drive(&self, drive_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.drives.remove(drive_id);
+    }
+
+    /// Create or update an item within the Shared Drive, which is recorded as an item change.
+    pub fn upsert(&self, drive_id: &str, item: Item) {
+        let mut state = self.state.lock().unwrap();
+
+        state
+            .drive_mut(drive_id)
+            .items
+            .insert(item.id.clone(), item.clone());
+
+        state.changes.push(Change::ItemChanged {
+            drive_id: drive_id.to_owned(),
+            item,
+        });
+    }
+
+    /// Create or update an item within the Shared Drive without recording a change,
+    /// like a change list which misses an item.
+    pub fn upsert_unreported(&self, drive_id: &str, item: Item) {
+        let mut state = self.state.lock().unwrap();
+
+        state
+            .drive_mut(drive_id)
+            .items
+            .insert(item.id.clone(), item);
+    }
+
+    /// Permanently delete an item, which is recorded as a removal.
+    /// Like Google Drive, the descendants of a folder are not reported separately.
+    pub fn remove(&self, drive_id: &str, item_id: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        state.drive_mut(drive_id).items.remove(item_id);
+        state.changes.push(Change::ItemRemoved {
+            drive_id: drive_id.to_owned(),
+            item_id: item_id.to_owned(),
+        });
+    }
+
+    /// Respond with an error to the next `times` requests whose path and query contain the pattern,
+    /// such as `fail("/files", 403, "rateLimitExceeded", 1)`.
+    pub fn fail(&self, pattern: &str, status: u16, reason: &str, times: usize) {
+        let mut state = self.state.lock().unwrap();
+
+        state.failures.push(Failure {
+            pattern: pattern.to_owned(),
+            status,
+            reason: reason.to_owned(),
+            retry_after: None,
+            remaining: times,
+        });
+    }
+
+    /// Respond to the next `times` requests whose path and query contain the pattern
+    /// with a rate limit error, optionally with a `Retry-After` header in seconds.
+    pub fn rate_limit(&self, pattern: &str, retry_after: Option<u64>, times: usize) {
+        let mut state = self.state.lock().unwrap();
+
+        state.failures.push(Failure {
+            pattern: pattern.to_owned(),
+            status: 403,
+            reason: "userRateLimitExceeded".to_owned(),
+            retry_after: retry_after.map(|seconds| seconds.to_string()),
+            remaining: times,
+        });
+    }
+
+    /// Like [`FakeDrive::rate_limit`], with a `Retry-After` header holding the HTTP date to retry after.
+    pub fn rate_limit_until(&self, pattern: &str, until: SystemTime, times: usize) {
+        let mut state = self.state.lock().unwrap();
+
+        state.failures.push(Failure {
+            pattern: pattern.to_owned(),
+            status: 403,
+            reason: "userRateLimitExceeded".to_owned(),
+            retry_after: Some(httpdate::fmt_http_date(until)),
+            remaining: times,
+        });
+    }
+
+    /// Limit the size of every page of the list endpoints, like Google Drive may do.
+    pub fn set_max_page_size(&self, size: usize) {
+        self.state.lock().unwrap().max_page_size = Some(size);
+    }
+
+    /// Delay every Drive API request, so concurrent requests overlap.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = Some(latency);
+    }
+
+    /// The largest number of Drive API requests handled at the same time so far.
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.state.lock().unwrap().max_in_flight
+    }
+
+    /// The path and query of every request received so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The bearer token of every authorised Drive API request so far.
+    pub fn access_tokens(&self) -> Vec<String> {
+        self.state.lock().unwrap().access_tokens.clone()
+    }
+
+    /// Reject every access token issued by the token endpoint so far.
+    pub fn revoke_issued_tokens(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        let issued = state.issued_tokens.clone();
+        state.revoked_tokens.extend(issued);
+    }
+
+    /// The `sub` claim of every JWT exchanged at the token endpoint so far.
+    pub fn token_subjects(&self) -> Vec<Option<String>> {
+        self.state.lock().unwrap().token_subjects.clone()
+    }
+}
+
+impl Drop for FakeDrive {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+impl State {
+    fn drive_mut(&mut self, drive_id: &str) -> &mut Drive {
+        self.drives
+            .get_mut(drive_id)
+            .unwrap_or_else(|| panic!("unknown fake drive: {}", drive_id))
+    }
+}
+
+/// Track a Drive API request as in flight, returning the latency to apply.
+fn start_request(state: &Mutex<State>) -> Option<Duration> {
+    let mut state = state.lock().unwrap();
+
+    state.in_flight += 1;
+    state.max_in_flight = state.max_in_flight.max(state.in_flight);
+    state.latency
+}
+
+fn handle(state: &Mutex<State>, request: Request<Bytes>) -> Response<Body> {
+    let mut state = state.lock().unwrap();
+
+    let path = request.uri().path().to_owned();
+    let query: HashMap<String, String> = request
+        .uri()
+        .query()
+        .map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let path_and_query = match request.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.clone(),
+    };
+
+    state.requests.push(path_and_query.clone());
+
+    if let Some(failure) = state.take_failure(&path_and_query) {
+        let mut response = error(failure.status, &failure.reason);
+
+        if let Some(retry_after) = &failure.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.parse().unwrap());
+        }
+
+        return response;
+    }
+
+    if request.method() == Method::POST && path == "/token" {
+        let form: HashMap<String, String> = form_urlencoded::parse(request.body())
+            .into_owned()
+            .collect();
+
+        if let Some(assertion) = form.get("assertion") {
+            state.token_subjects.push(jwt_subject(assertion));
+        }
+
+        let access_token = format!("{}-{}", ACCESS_TOKEN, state.issued_tokens.len());
+        state.issued_tokens.push(access_token.clone());
+
+        return json_response(json!({
+            "access_token": access_token,
+            "expires_in": 3600,
+            "token_type": "Bearer",
+        }));
+    }
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .filter(|token| token.starts_with(ACCESS_TOKEN))
+        .filter(|token| !state.revoked_tokens.iter().any(|revoked| revoked == token));
+
+    match token {
+        Some(token) => state.access_tokens.push(token.to_owned()),
+        None => return error(401, "authError"),
+    }
+
+    let route = match path.strip_prefix("/drive/v3") {
+        Some(route) if request.method() == Method::GET => route,
+        _ => return error(404, "notFound"),
+    };
+
+    match route {
+        "/files" => list_files(&state, &query),
+        "/files/root" => get_root(&state),
+        "/changes" => list_changes(&state, &query),
+        "/changes/startPageToken" => start_page_token(&state, &query),
+        "/drives" => list_drives(&state, &query),
+        _ => match route.strip_prefix("/drives/") {
+            Some(drive_id) => get_drive(&state, drive_id),
+            None => error(404, "notFound"),
+        },
+    }
+}
+
+fn list_files(state: &State, query: &HashMap<String, String>) -> Response<Body> {
+    let corpora = query.get("corpora").map(String::as_str);
+
+    let (drive_id, drive) = match (corpora, query.get("driveId")) {
+        (Some("drive"), Some(drive_id)) => match state.shared_drive(drive_id) {
+            Some(drive) => (Some(drive_id), drive),
+            None => return error(404, "notFound"),
+        },
+        (Some("user"), None) => match state.my_drive_id() {
+            Some(root_id) => (None, &state.drives[root_id]),
+            None => return error(404, "notFound"),
+        },
+        _ => return error(400, "invalid"),
+    };
+
+    let items: Vec<Value> = drive
+        .items
+        .values()
+        .map(|item| item.to_json(drive_id.map(String::as_str)))
+        .collect();
+
+    let (items, next_page_token) = paginate(state, items, query);
+
+    json_response(json!({
+        "files": items,
+        "nextPageToken": next_page_token,
+    }))
+}
+
+/// The drive of the changes, which is My Drive without a drive ID.
+fn changes_drive_id<'a>(
+    state: &'a State,
+    query: &'a HashMap<String, String>,
+) -> Option<&'a String> {
+    match query.get("driveId") {
+        Some(drive_id) => state.shared_drive(drive_id).map(|_| drive_id),
+        None => state.my_drive_id(),
+    }
+}
+
+fn list_changes(state: &State, query: &HashMap<String, String>) -> Response<Body> {
+    let drive_id = match changes_drive_id(state, query) {
+        Some(drive_id) => drive_id,
+        None => return error(404, "notFound"),
+    };
+
+    let my_drive = state.drives[drive_id].my_drive;
+
+    let start: usize = match query.get("pageToken").and_then(|token| token.parse().ok()) {
+        Some(start) => start,
+        None => return error(400, "invalid"),
+    };
+
+    let page_size = page_size(state, query);
+
+    // The page token is the index within the change log of all drives.
+    let mut changes = Vec::new();
+    let mut next_page_token = None;
+    for (index, change) in state.changes.iter().enumerate().skip(start) {
+        if change.drive_id() != drive_id {
+            continue;
+        }
+
+        if changes.len() == page_size {
+            next_page_token = Some(index.to_string());
+            break;
+        }
+
+        changes.push(change.to_json(my_drive));
+    }
+
+    match next_page_token {
+        Some(next_page_token) => json_response(json!({
+            "changes": changes,
+            "nextPageToken": next_page_token,
+        })),
+        None => json_response(json!({
+            "changes": changes,
+            "newStartPageToken": state.changes.len().to_string(),
+        })),
+    }
+}
+
+fn start_page_token(state: &State, query: &HashMap<String, String>) -> Response<Body> {
+    match changes_drive_id(state, query) {
+        Some(_) => json_response(json!({
+            "startPageToken": state.changes.len().to_string(),
+        })),
+        None => error(404, "notFound"),
+    }
+}
+
+fn get_root(state: &State) -> Response<Body> {
+    match state.my_drive_id() {
+        Some(root_id) => json_response(json!({
+            "id": root_id,
+            "name": state.drives[root_id].name,
+        })),
+        None => error(404, "notFound"),
+    }
+}
+
+fn get_drive(state: &State, drive_id: &str) -> Response<Body> {
+    match state.shared_drive(drive_id) {
+        Some(drive) => json_response(json!({
+            "id": drive_id,
+            "name": drive.name,
+        })),
+        None => error(404, "notFound"),
+    }
+}
+
+fn list_drives(state: &State, query: &HashMap<String, String>) -> Response<Body> {
+    let drives: Vec<Value> = state
+        .drives
+        .iter()
+        .filter(|(_, drive)| !drive.my_drive)
+        .map(|(id, drive)| json!({ "id": id, "name": drive.name }))
+        .collect();
+
+    let (drives, next_page_token) = paginate(state, drives, query);
+
+    json_response(json!({
+        "drives": drives,
+        "nextPageToken": next_page_token,
+    }))
+}
+
+fn page_size(state: &State, query: &HashMap<String, String>) -> usize {
+    let size = query
+        .get("pageSize")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(PAGE_SIZE);
+
+    match state.max_page_size {
+        Some(max_page_size) => size.min(max_page_size),
+        None => size,
+    }
+}
+
+/// Select a single page of the values, where the page token is the offset of the page.
+fn paginate(
+    state: &State,
+    values: Vec<Value>,
+    query: &HashMap<String, String>,
+) -> (Vec<Value>, Option<String>) {
+    let start: usize = query
+        .get("pageToken")
+        .and_then(|token| token.parse().ok())
+        .unwrap_or(0);
+
+    let end = start + page_size(state, query);
+    let next_page_token = match end < values.len() {
+        true => Some(end.to_string()),
+        false => None,
+    };
+
+    let page = values.into_iter().skip(start).take(end - start).collect();
+    (page, next_page_token)
+}
+
+fn json_response(value: Value) -> Response<Body> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+/// An error response in the format of the Google APIs.
+fn error(status: u16, reason: &str) -> Response<Body> {
+    let status = StatusCode::from_u16(status).unwrap();
+    let body = json!({
+        "error": {
+            "code": status.as_u16(),
+            "message": status.canonical_reason(),
+            "errors": [{ "reason": reason, "message": status.canonical_reason() }],
+        }
+    });
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// The `sub` claim of the JWT, whose signature is not verified.
+fn jwt_subject(jwt: &str) -> Option<String> {
+    let claims = jwt.split('.').nth(1)?;
+    let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: Value = serde_json::from_slice(&claims).ok()?;
+
+    claims.get("sub")?.as_str().map(ToOwned::to_owned)
+}
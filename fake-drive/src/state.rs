@@ -0,0 +1,118 @@
+use crate::Item;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The in-memory tree and change log of all fake Shared Drives and My Drive.
+#[derive(Debug, Default)]
+pub(crate) struct State {
+    pub drives: BTreeMap<String, Drive>,
+    pub changes: Vec<Change>,
+    pub failures: Vec<Failure>,
+    pub requests: Vec<String>,
+    pub token_subjects: Vec<Option<String>>,
+    pub access_tokens: Vec<String>,
+    /// The access tokens issued by the token endpoint, and those which are no longer accepted.
+    pub issued_tokens: Vec<String>,
+    pub revoked_tokens: Vec<String>,
+    /// The largest page size, regardless of the requested page size.
+    pub max_page_size: Option<usize>,
+    /// The delay before every Drive API request is handled.
+    pub latency: Option<Duration>,
+    /// The Drive API requests currently being handled, and the most at any time.
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct Drive {
+    pub name: String,
+    pub items: BTreeMap<String, Item>,
+    /// Whether this is the My Drive of the user instead of a Shared Drive.
+    pub my_drive: bool,
+}
+
+/// A single entry of the change log, holding a snapshot of the item at the time of the change.
+#[derive(Debug)]
+pub(crate) enum Change {
+    DriveChanged { drive_id: String, name: String },
+    ItemChanged { drive_id: String, item: Item },
+    ItemRemoved { drive_id: String, item_id: String },
+}
+
+impl Change {
+    pub fn drive_id(&self) -> &str {
+        match self {
+            Self::DriveChanged { drive_id, .. } => drive_id,
+            Self::ItemChanged { drive_id, .. } => drive_id,
+            Self::ItemRemoved { drive_id, .. } => drive_id,
+        }
+    }
+
+    /// The change resource as returned by the Google Drive API.
+    pub fn to_json(&self, my_drive: bool) -> Value {
+        match self {
+            Self::DriveChanged { drive_id, name } => json!({
+                "changeType": "drive",
+                "driveId": drive_id,
+                "removed": false,
+                "drive": { "id": drive_id, "name": name },
+            }),
+            Self::ItemChanged { drive_id, item } => {
+                let drive_id = match my_drive {
+                    true => None,
+                    false => Some(drive_id.as_str()),
+                };
+
+                json!({
+                    "changeType": "file",
+                    "fileId": item.id,
+                    "removed": false,
+                    "file": item.to_json(drive_id),
+                })
+            }
+            Self::ItemRemoved { item_id, .. } => json!({
+                "changeType": "file",
+                "fileId": item_id,
+                "removed": true,
+            }),
+        }
+    }
+}
+
+/// A programmed error response for the requests matching the pattern.
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub pattern: String,
+    pub status: u16,
+    pub reason: String,
+    /// The value of the `Retry-After` header, either in seconds or an HTTP date.
+    pub retry_after: Option<String>,
+    pub remaining: usize,
+}
+
+impl State {
+    /// The ID of My Drive, which is also the ID of its root folder.
+    pub fn my_drive_id(&self) -> Option<&String> {
+        self.drives
+            .iter()
+            .find(|(_, drive)| drive.my_drive)
+            .map(|(id, _)| id)
+    }
+
+    /// The Shared Drive with the provided ID.
+    pub fn shared_drive(&self, drive_id: &str) -> Option<&Drive> {
+        self.drives.get(drive_id).filter(|drive| !drive.my_drive)
+    }
+
+    /// Take the next programmed failure for the path and query of the request, if any.
+    pub fn take_failure(&mut self, request: &str) -> Option<&Failure> {
+        let failure = self
+            .failures
+            .iter_mut()
+            .find(|failure| failure.remaining > 0 && request.contains(&failure.pattern))?;
+
+        failure.remaining -= 1;
+        Some(failure)
+    }
+}
@@ -0,0 +1,133 @@
+-- Store the MIME type of every file and allow files without binary content.
+-- Google Workspace documents and shortcuts do not have an md5 checksum nor a size.
+-- SQLite cannot drop a NOT NULL constraint, so both tables are rebuilt.
+
+DROP VIEW paths;
+DROP VIEW path_changelog;
+
+CREATE TABLE files_new (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'parent' TEXT NOT NULL,
+    'mime_type' TEXT NOT NULL,
+    'md5' TEXT,
+    'size' BIGINT,
+    PRIMARY KEY('id', 'drive_id'),
+    FOREIGN KEY('drive_id') REFERENCES drives('id') ON DELETE CASCADE,
+    -- Deferred constraint so integrity is checked at the end of the transaction.
+    FOREIGN KEY('parent', 'drive_id') REFERENCES folders('id', 'drive_id') ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED
+);
+
+-- Existing files were only stored when they had binary content, their kind is derived from the MIME type.
+INSERT INTO files_new ('id', 'drive_id', 'name', 'trashed', 'parent', 'mime_type', 'md5', 'size')
+SELECT id, drive_id, name, trashed, parent, 'application/octet-stream', md5, size FROM files;
+
+DROP TABLE files;
+ALTER TABLE files_new RENAME TO files;
+
+CREATE INDEX files_parent ON files ('parent', 'drive_id');
+
+CREATE TABLE file_changelog_new (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'deleted' BOOLEAN NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'parent' TEXT NOT NULL,
+    'mime_type' TEXT NOT NULL,
+    'md5' TEXT,
+    'size' BIGINT,
+    PRIMARY KEY('id', 'drive_id', 'deleted')
+);
+
+INSERT INTO file_changelog_new ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'mime_type', 'md5', 'size')
+SELECT id, drive_id, deleted, name, trashed, parent, 'application/octet-stream', md5, size FROM file_changelog;
+
+DROP TABLE file_changelog;
+ALTER TABLE file_changelog_new RENAME TO file_changelog;
+
+-- File triggers
+CREATE TRIGGER file_delete
+AFTER DELETE ON files
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'mime_type', 'md5', 'size')
+    VALUES (OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, OLD.parent, OLD.mime_type, OLD.md5, OLD.size);
+END;
+
+CREATE TRIGGER file_update
+AFTER UPDATE ON files
+WHEN OLD.name <> NEW.name OR OLD.trashed <> NEW.trashed OR OLD.parent <> NEW.parent OR OLD.mime_type <> NEW.mime_type OR OLD.md5 IS NOT NEW.md5 OR OLD.size IS NOT NEW.size
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'mime_type', 'md5', 'size')
+    VALUES
+        (OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, OLD.parent, OLD.mime_type, OLD.md5, OLD.size),
+        (NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, NEW.parent, NEW.mime_type, NEW.md5, NEW.size);
+END;
+
+CREATE TRIGGER file_create
+AFTER INSERT ON files
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'mime_type', 'md5', 'size')
+    VALUES (NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, NEW.parent, NEW.mime_type, NEW.md5, NEW.size);
+END;
+
+-- Views (unchanged)
+CREATE VIEW paths AS
+    WITH parents AS (
+        -- Initial folders
+        SELECT 1 as 'folder', f.id, f.drive_id, f.parent, "/" || f.name as path FROM folders f
+
+        UNION ALL
+
+        -- Initial files
+        SELECT 0 as 'folder', f.id, f.drive_id, f.parent, "/" || f.name as path FROM files f
+
+        UNION ALL
+
+        -- Recursive clause (using p.id to preserve original id)
+        SELECT p.folder, p.id, f.drive_id, f.parent, "/" || f.name || p.path as path
+        FROM folders f, parents p
+        WHERE f.id = p.parent AND f.parent IS NOT NULL
+    )
+    SELECT p.folder, p.id, p.drive_id, p.path FROM parents p
+    WHERE p.drive_id = p.parent;
+
+CREATE VIEW path_changelog AS
+    WITH
+        changelog_paths AS (
+            -- Initial folders
+            SELECT 1 as 'folder', f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM folder_changelog f
+
+            UNION ALL
+
+            -- Initial files
+            SELECT 0 as 'folder', f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM file_changelog f
+
+            UNION ALL
+
+            -- Recursive clause (using p.id to preserve original id)
+            SELECT p.folder, p.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name || p.path as path
+            FROM folder_changelog f, changelog_paths p
+            WHERE f.id = p.parent AND f.drive_id = p.drive_id
+        ),
+        full_paths AS (
+            -- Initial changed paths
+            SELECT p.folder, p.id, p.drive_id, p.parent, p.deleted, p.trashed, p.path FROM changelog_paths p
+            -- Not exists to only get the "full" path of each id.
+            WHERE NOT EXISTS (
+                SELECT * FROM changelog_paths p2
+                WHERE p2.id = p.parent
+            )
+
+            UNION ALL
+
+            -- Recursive clause
+            SELECT p.folder, p.id, f.drive_id, f.parent, p.deleted, p.trashed, "/" || f.name || p.path as path
+            FROM folders f
+            INNER JOIN full_paths p ON f.id = p.parent AND f.drive_id = p.drive_id
+            WHERE f.parent IS NOT NULL
+        )
+    SELECT p.folder, p.id, p.drive_id, p.deleted, p.trashed, p.path FROM full_paths p
+    WHERE p.parent = p.drive_id;
@@ -0,0 +1,107 @@
+-- Distinguish binary files from Google Workspace documents and shortcuts.
+DROP VIEW paths;
+DROP VIEW path_changelog;
+
+DROP TRIGGER file_delete;
+DROP TRIGGER file_update;
+DROP TRIGGER file_create;
+
+ALTER TABLE files ADD COLUMN 'kind' TEXT NOT NULL DEFAULT 'binary';
+ALTER TABLE file_changelog ADD COLUMN 'kind' TEXT NOT NULL DEFAULT 'binary';
+
+UPDATE files SET kind = CASE
+    WHEN mime_type = 'application/vnd.google-apps.shortcut' THEN 'shortcut'
+    WHEN mime_type LIKE 'application/vnd.google-apps.%' THEN 'document'
+    ELSE 'binary'
+END;
+
+UPDATE file_changelog SET kind = CASE
+    WHEN mime_type = 'application/vnd.google-apps.shortcut' THEN 'shortcut'
+    WHEN mime_type LIKE 'application/vnd.google-apps.%' THEN 'document'
+    ELSE 'binary'
+END;
+
+-- File triggers
+CREATE TRIGGER file_delete
+AFTER DELETE ON files
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size')
+    VALUES (OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, OLD.parent, OLD.kind, OLD.mime_type, OLD.md5, OLD.size);
+END;
+
+CREATE TRIGGER file_update
+AFTER UPDATE ON files
+WHEN OLD.name <> NEW.name OR OLD.trashed <> NEW.trashed OR OLD.parent <> NEW.parent OR OLD.kind <> NEW.kind OR OLD.mime_type <> NEW.mime_type OR OLD.md5 IS NOT NEW.md5 OR OLD.size IS NOT NEW.size
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size')
+    VALUES
+        (OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, OLD.parent, OLD.kind, OLD.mime_type, OLD.md5, OLD.size),
+        (NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, NEW.parent, NEW.kind, NEW.mime_type, NEW.md5, NEW.size);
+END;
+
+CREATE TRIGGER file_create
+AFTER INSERT ON files
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size')
+    VALUES (NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, NEW.parent, NEW.kind, NEW.mime_type, NEW.md5, NEW.size);
+END;
+
+-- Views
+-- The `folder` column is replaced by a `kind` column: folder, binary, document or shortcut.
+CREATE VIEW paths AS
+    WITH parents AS (
+        -- Initial folders
+        SELECT 'folder' as 'kind', f.id, f.drive_id, f.parent, "/" || f.name as path FROM folders f
+
+        UNION ALL
+
+        -- Initial files
+        SELECT f.kind, f.id, f.drive_id, f.parent, "/" || f.name as path FROM files f
+
+        UNION ALL
+
+        -- Recursive clause (using p.id to preserve original id)
+        SELECT p.kind, p.id, f.drive_id, f.parent, "/" || f.name || p.path as path
+        FROM folders f, parents p
+        WHERE f.id = p.parent AND f.parent IS NOT NULL
+    )
+    SELECT p.kind, p.id, p.drive_id, p.path FROM parents p
+    WHERE p.drive_id = p.parent;
+
+CREATE VIEW path_changelog AS
+    WITH
+        changelog_paths AS (
+            -- Initial folders
+            SELECT 'folder' as 'kind', f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM folder_changelog f
+
+            UNION ALL
+
+            -- Initial files
+            SELECT f.kind, f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM file_changelog f
+
+            UNION ALL
+
+            -- Recursive clause (using p.id to preserve original id)
+            SELECT p.kind, p.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name || p.path as path
+            FROM folder_changelog f, changelog_paths p
+            WHERE f.id = p.parent AND f.drive_id = p.drive_id
+        ),
+        full_paths AS (
+            -- Initial changed paths
+            SELECT p.kind, p.id, p.drive_id, p.parent, p.deleted, p.trashed, p.path FROM changelog_paths p
+            -- Not exists to only get the "full" path of each id.
+            WHERE NOT EXISTS (
+                SELECT * FROM changelog_paths p2
+                WHERE p2.id = p.parent
+            )
+
+            UNION ALL
+
+            -- Recursive clause
+            SELECT p.kind, p.id, f.drive_id, f.parent, p.deleted, p.trashed, "/" || f.name || p.path as path
+            FROM folders f
+            INNER JOIN full_paths p ON f.id = p.parent AND f.drive_id = p.drive_id
+            WHERE f.parent IS NOT NULL
+        )
+    SELECT p.kind, p.id, p.drive_id, p.deleted, p.trashed, p.path FROM full_paths p
+    WHERE p.parent = p.drive_id;
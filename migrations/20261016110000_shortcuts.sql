@@ -0,0 +1,117 @@
+-- Store the target of shortcuts and expose shortcuts as links or as resolved copies of their target.
+DROP VIEW paths;
+
+DROP TRIGGER file_delete;
+DROP TRIGGER file_update;
+DROP TRIGGER file_create;
+
+ALTER TABLE files ADD COLUMN 'target_id' TEXT;
+ALTER TABLE files ADD COLUMN 'target_mime_type' TEXT;
+ALTER TABLE file_changelog ADD COLUMN 'target_id' TEXT;
+ALTER TABLE file_changelog ADD COLUMN 'target_mime_type' TEXT;
+
+-- Speeds up resolving the shortcuts pointing to a target.
+CREATE INDEX files_target ON files ('target_id');
+
+-- File triggers
+CREATE TRIGGER file_delete
+AFTER DELETE ON files
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    VALUES (OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, OLD.parent, OLD.kind, OLD.mime_type, OLD.md5, OLD.size, OLD.target_id, OLD.target_mime_type);
+END;
+
+CREATE TRIGGER file_update
+AFTER UPDATE ON files
+WHEN OLD.name <> NEW.name OR OLD.trashed <> NEW.trashed OR OLD.parent <> NEW.parent OR OLD.kind <> NEW.kind OR OLD.mime_type <> NEW.mime_type OR OLD.md5 IS NOT NEW.md5 OR OLD.size IS NOT NEW.size OR OLD.target_id IS NOT NEW.target_id
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    VALUES
+        (OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, OLD.parent, OLD.kind, OLD.mime_type, OLD.md5, OLD.size, OLD.target_id, OLD.target_mime_type),
+        (NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, NEW.parent, NEW.kind, NEW.mime_type, NEW.md5, NEW.size, NEW.target_id, NEW.target_mime_type);
+END;
+
+CREATE TRIGGER file_create
+AFTER INSERT ON files
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    VALUES (NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, NEW.parent, NEW.kind, NEW.mime_type, NEW.md5, NEW.size, NEW.target_id, NEW.target_mime_type);
+END;
+
+-- Views
+-- Shortcuts are exposed as links, with the `trashed` column added.
+CREATE VIEW paths AS
+    WITH parents AS (
+        -- Initial folders
+        SELECT 'folder' as 'kind', f.id, f.drive_id, f.parent, f.trashed, "/" || f.name as path FROM folders f
+
+        UNION ALL
+
+        -- Initial files
+        SELECT f.kind, f.id, f.drive_id, f.parent, f.trashed, "/" || f.name as path FROM files f
+
+        UNION ALL
+
+        -- Recursive clause (using p.id to preserve original id)
+        SELECT p.kind, p.id, f.drive_id, f.parent, p.trashed, "/" || f.name || p.path as path
+        FROM folders f, parents p
+        WHERE f.id = p.parent AND f.parent IS NOT NULL
+    )
+    SELECT p.kind, p.id, p.drive_id, p.trashed, p.path FROM parents p
+    WHERE p.drive_id = p.parent;
+
+-- Shortcuts are replaced by their target, including the full subtree when the target is a folder.
+-- The target may live in another synchronised Shared Drive.
+-- Shortcuts within a resolved subtree are not resolved again to prevent cycles.
+CREATE VIEW resolved_paths AS
+    WITH
+        shortcuts AS (
+            SELECT p.drive_id, p.path, f.target_id
+            FROM paths p
+            INNER JOIN files f ON f.id = p.id AND f.drive_id = p.drive_id
+            WHERE p.kind = 'shortcut'
+        ),
+        targets AS (
+            -- Target folders (target_drive_id is used to walk the subtree within the target's drive)
+            SELECT 'folder' as 'kind', f.id, s.drive_id, f.drive_id as target_drive_id, f.trashed, s.path
+            FROM shortcuts s
+            INNER JOIN folders f ON f.id = s.target_id
+
+            UNION ALL
+
+            -- Target files
+            SELECT f.kind, f.id, s.drive_id, f.drive_id as target_drive_id, f.trashed, s.path
+            FROM shortcuts s
+            INNER JOIN files f ON f.id = s.target_id
+
+            UNION ALL
+
+            -- Recursive clause for the folders within a target folder
+            SELECT 'folder' as 'kind', f.id, t.drive_id, t.target_drive_id, f.trashed, t.path || "/" || f.name as path
+            FROM folders f
+            INNER JOIN targets t ON f.parent = t.id AND f.drive_id = t.target_drive_id
+            WHERE t.kind = 'folder'
+
+            UNION ALL
+
+            -- Recursive clause for the files within a target folder
+            SELECT f.kind, f.id, t.drive_id, t.target_drive_id, f.trashed, t.path || "/" || f.name as path
+            FROM files f
+            INNER JOIN targets t ON f.parent = t.id AND f.drive_id = t.target_drive_id
+            WHERE t.kind = 'folder'
+        )
+    SELECT p.kind, p.id, p.drive_id, p.trashed, p.path FROM paths p
+    WHERE p.kind <> 'shortcut'
+
+    UNION ALL
+
+    SELECT t.kind, t.id, t.drive_id, t.trashed, t.path FROM targets t
+
+    UNION ALL
+
+    -- Shortcuts pointing to a target which is not synchronised remain links.
+    SELECT p.kind, p.id, p.drive_id, p.trashed, p.path FROM paths p
+    INNER JOIN files f ON f.id = p.id AND f.drive_id = p.drive_id
+    WHERE p.kind = 'shortcut'
+    AND NOT EXISTS (SELECT 1 FROM folders t WHERE t.id = f.target_id)
+    AND NOT EXISTS (SELECT 1 FROM files t WHERE t.id = f.target_id);
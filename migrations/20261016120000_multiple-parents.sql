@@ -0,0 +1,304 @@
+-- Move the parent of every folder and file into a `parents` join table,
+-- so items with multiple parents keep all of their paths.
+DROP VIEW paths;
+DROP VIEW resolved_paths;
+DROP VIEW path_changelog;
+
+DROP TRIGGER folder_delete;
+DROP TRIGGER folder_update;
+DROP TRIGGER folder_create;
+DROP TRIGGER file_delete;
+DROP TRIGGER file_update;
+DROP TRIGGER file_create;
+
+-- Keep the existing parents around while the tables are rebuilt.
+CREATE TABLE parents_old AS
+    SELECT id, drive_id, parent FROM folders WHERE parent IS NOT NULL
+    UNION ALL
+    SELECT id, drive_id, parent FROM files;
+
+-- Models
+CREATE TABLE files_new (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'kind' TEXT NOT NULL,
+    'mime_type' TEXT NOT NULL,
+    'md5' TEXT,
+    'size' BIGINT,
+    'target_id' TEXT,
+    'target_mime_type' TEXT,
+    PRIMARY KEY('id', 'drive_id'),
+    FOREIGN KEY('drive_id') REFERENCES drives('id') ON DELETE CASCADE
+);
+
+INSERT INTO files_new ('id', 'drive_id', 'name', 'trashed', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+SELECT id, drive_id, name, trashed, kind, mime_type, md5, size, target_id, target_mime_type FROM files;
+
+DROP TABLE files;
+ALTER TABLE files_new RENAME TO files;
+
+CREATE INDEX files_target ON files ('target_id');
+
+CREATE TABLE folders_new (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    PRIMARY KEY('id', 'drive_id'),
+    FOREIGN KEY('drive_id') REFERENCES drives('id') ON DELETE CASCADE
+);
+
+INSERT INTO folders_new ('id', 'drive_id', 'name', 'trashed')
+SELECT id, drive_id, name, trashed FROM folders;
+
+DROP TABLE folders;
+ALTER TABLE folders_new RENAME TO folders;
+
+-- The root folder of a drive is the only item without any parent.
+CREATE TABLE parents (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'parent' TEXT NOT NULL,
+    PRIMARY KEY('id', 'drive_id', 'parent'),
+    -- Deferred constraint so integrity is checked at the end of the transaction.
+    FOREIGN KEY('parent', 'drive_id') REFERENCES folders('id', 'drive_id') ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED
+);
+
+-- Create an index on the parent. This massively speeds up the FK constraint.
+CREATE INDEX parents_parent ON parents ('parent', 'drive_id');
+
+INSERT INTO parents ('id', 'drive_id', 'parent')
+SELECT id, drive_id, parent FROM parents_old;
+
+DROP TABLE parents_old;
+
+-- Changelogs
+-- Every row describes the item under one of its parents.
+CREATE TABLE folder_changelog_new (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'deleted' BOOLEAN NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'parent' TEXT NOT NULL,
+    PRIMARY KEY('id', 'drive_id', 'deleted', 'parent')
+);
+
+INSERT INTO folder_changelog_new ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent')
+SELECT id, drive_id, deleted, name, trashed, parent FROM folder_changelog WHERE parent IS NOT NULL;
+
+DROP TABLE folder_changelog;
+ALTER TABLE folder_changelog_new RENAME TO folder_changelog;
+
+CREATE TABLE file_changelog_new (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'deleted' BOOLEAN NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'parent' TEXT NOT NULL,
+    'kind' TEXT NOT NULL,
+    'mime_type' TEXT NOT NULL,
+    'md5' TEXT,
+    'size' BIGINT,
+    'target_id' TEXT,
+    'target_mime_type' TEXT,
+    PRIMARY KEY('id', 'drive_id', 'deleted', 'parent')
+);
+
+INSERT INTO file_changelog_new ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+SELECT id, drive_id, deleted, name, trashed, parent, kind, mime_type, md5, size, target_id, target_mime_type FROM file_changelog;
+
+DROP TABLE file_changelog;
+ALTER TABLE file_changelog_new RENAME TO file_changelog;
+
+-- Parent triggers
+-- An item is created or deleted under a parent when the parent is linked or unlinked.
+-- Items must therefore be inserted before their parents are linked.
+CREATE TRIGGER parent_create
+AFTER INSERT ON parents
+BEGIN
+    INSERT INTO folder_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent')
+    SELECT f.id, f.drive_id, 0, f.name, f.trashed, NEW.parent FROM folders f
+    WHERE f.id = NEW.id AND f.drive_id = NEW.drive_id;
+
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    SELECT f.id, f.drive_id, 0, f.name, f.trashed, NEW.parent, f.kind, f.mime_type, f.md5, f.size, f.target_id, f.target_mime_type FROM files f
+    WHERE f.id = NEW.id AND f.drive_id = NEW.drive_id;
+END;
+
+CREATE TRIGGER parent_delete
+AFTER DELETE ON parents
+BEGIN
+    INSERT INTO folder_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent')
+    SELECT f.id, f.drive_id, 1, f.name, f.trashed, OLD.parent FROM folders f
+    WHERE f.id = OLD.id AND f.drive_id = OLD.drive_id;
+
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    SELECT f.id, f.drive_id, 1, f.name, f.trashed, OLD.parent, f.kind, f.mime_type, f.md5, f.size, f.target_id, f.target_mime_type FROM files f
+    WHERE f.id = OLD.id AND f.drive_id = OLD.drive_id;
+END;
+
+-- Folder triggers
+-- Unlink the parents before the folder is gone, so the deletion ends up in the changelog.
+CREATE TRIGGER folder_delete
+BEFORE DELETE ON folders
+BEGIN
+    DELETE FROM parents WHERE id = OLD.id AND drive_id = OLD.drive_id;
+END;
+
+CREATE TRIGGER folder_update
+AFTER UPDATE ON folders
+WHEN OLD.name <> NEW.name OR OLD.trashed <> NEW.trashed
+BEGIN
+    INSERT INTO folder_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent')
+    SELECT OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, p.parent FROM parents p
+    WHERE p.id = OLD.id AND p.drive_id = OLD.drive_id;
+
+    INSERT INTO folder_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent')
+    SELECT NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, p.parent FROM parents p
+    WHERE p.id = NEW.id AND p.drive_id = NEW.drive_id;
+END;
+
+-- File triggers
+CREATE TRIGGER file_delete
+BEFORE DELETE ON files
+BEGIN
+    DELETE FROM parents WHERE id = OLD.id AND drive_id = OLD.drive_id;
+END;
+
+CREATE TRIGGER file_update
+AFTER UPDATE ON files
+WHEN OLD.name <> NEW.name OR OLD.trashed <> NEW.trashed OR OLD.kind <> NEW.kind OR OLD.mime_type <> NEW.mime_type OR OLD.md5 IS NOT NEW.md5 OR OLD.size IS NOT NEW.size OR OLD.target_id IS NOT NEW.target_id
+BEGIN
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    SELECT OLD.id, OLD.drive_id, 1, OLD.name, OLD.trashed, p.parent, OLD.kind, OLD.mime_type, OLD.md5, OLD.size, OLD.target_id, OLD.target_mime_type FROM parents p
+    WHERE p.id = OLD.id AND p.drive_id = OLD.drive_id;
+
+    INSERT INTO file_changelog ('id', 'drive_id', 'deleted', 'name', 'trashed', 'parent', 'kind', 'mime_type', 'md5', 'size', 'target_id', 'target_mime_type')
+    SELECT NEW.id, NEW.drive_id, 0, NEW.name, NEW.trashed, p.parent, NEW.kind, NEW.mime_type, NEW.md5, NEW.size, NEW.target_id, NEW.target_mime_type FROM parents p
+    WHERE p.id = NEW.id AND p.drive_id = NEW.drive_id;
+END;
+
+-- Views
+-- One path is emitted for every parent of an item (and every parent of its ancestors).
+CREATE VIEW paths AS
+    WITH item_paths AS (
+        -- Initial folders
+        SELECT 'folder' as 'kind', f.id, f.drive_id, p.parent, f.trashed, "/" || f.name as path FROM folders f
+        INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+
+        UNION ALL
+
+        -- Initial files
+        SELECT f.kind, f.id, f.drive_id, p.parent, f.trashed, "/" || f.name as path FROM files f
+        INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+
+        UNION ALL
+
+        -- Recursive clause (using i.id to preserve original id)
+        SELECT i.kind, i.id, f.drive_id, p.parent, i.trashed, "/" || f.name || i.path as path
+        FROM item_paths i
+        INNER JOIN folders f ON f.id = i.parent AND f.drive_id = i.drive_id
+        INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+    )
+    SELECT i.kind, i.id, i.drive_id, i.trashed, i.path FROM item_paths i
+    WHERE i.drive_id = i.parent;
+
+-- Shortcuts are replaced by their target, including the full subtree when the target is a folder.
+-- The target may live in another synchronised Shared Drive.
+-- Shortcuts within a resolved subtree are not resolved again to prevent cycles.
+CREATE VIEW resolved_paths AS
+    WITH
+        shortcuts AS (
+            SELECT p.drive_id, p.path, f.target_id
+            FROM paths p
+            INNER JOIN files f ON f.id = p.id AND f.drive_id = p.drive_id
+            WHERE p.kind = 'shortcut'
+        ),
+        targets AS (
+            -- Target folders (target_drive_id is used to walk the subtree within the target's drive)
+            SELECT 'folder' as 'kind', f.id, s.drive_id, f.drive_id as target_drive_id, f.trashed, s.path
+            FROM shortcuts s
+            INNER JOIN folders f ON f.id = s.target_id
+
+            UNION ALL
+
+            -- Target files
+            SELECT f.kind, f.id, s.drive_id, f.drive_id as target_drive_id, f.trashed, s.path
+            FROM shortcuts s
+            INNER JOIN files f ON f.id = s.target_id
+
+            UNION ALL
+
+            -- Recursive clause for the folders within a target folder
+            SELECT 'folder' as 'kind', f.id, t.drive_id, t.target_drive_id, f.trashed, t.path || "/" || f.name as path
+            FROM targets t
+            INNER JOIN parents p ON p.parent = t.id AND p.drive_id = t.target_drive_id
+            INNER JOIN folders f ON f.id = p.id AND f.drive_id = p.drive_id
+            WHERE t.kind = 'folder'
+
+            UNION ALL
+
+            -- Recursive clause for the files within a target folder
+            SELECT f.kind, f.id, t.drive_id, t.target_drive_id, f.trashed, t.path || "/" || f.name as path
+            FROM targets t
+            INNER JOIN parents p ON p.parent = t.id AND p.drive_id = t.target_drive_id
+            INNER JOIN files f ON f.id = p.id AND f.drive_id = p.drive_id
+            WHERE t.kind = 'folder'
+        )
+    SELECT p.kind, p.id, p.drive_id, p.trashed, p.path FROM paths p
+    WHERE p.kind <> 'shortcut'
+
+    UNION ALL
+
+    SELECT t.kind, t.id, t.drive_id, t.trashed, t.path FROM targets t
+
+    UNION ALL
+
+    -- Shortcuts pointing to a target which is not synchronised remain links.
+    SELECT p.kind, p.id, p.drive_id, p.trashed, p.path FROM paths p
+    INNER JOIN files f ON f.id = p.id AND f.drive_id = p.drive_id
+    WHERE p.kind = 'shortcut'
+    AND NOT EXISTS (SELECT 1 FROM folders t WHERE t.id = f.target_id)
+    AND NOT EXISTS (SELECT 1 FROM files t WHERE t.id = f.target_id);
+
+CREATE VIEW path_changelog AS
+    WITH
+        changelog_paths AS (
+            -- Initial folders
+            SELECT 'folder' as 'kind', f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM folder_changelog f
+
+            UNION ALL
+
+            -- Initial files
+            SELECT f.kind, f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM file_changelog f
+
+            UNION ALL
+
+            -- Recursive clause (using p.id to preserve original id)
+            SELECT p.kind, p.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name || p.path as path
+            FROM folder_changelog f, changelog_paths p
+            WHERE f.id = p.parent AND f.drive_id = p.drive_id
+        ),
+        full_paths AS (
+            -- Initial changed paths
+            SELECT p.kind, p.id, p.drive_id, p.parent, p.deleted, p.trashed, p.path FROM changelog_paths p
+            -- Not exists to only get the "full" path of each id.
+            WHERE NOT EXISTS (
+                SELECT * FROM changelog_paths p2
+                WHERE p2.id = p.parent
+            )
+
+            UNION ALL
+
+            -- Recursive clause, following every parent of the unchanged ancestors
+            SELECT p.kind, p.id, f.drive_id, l.parent, p.deleted, p.trashed, "/" || f.name || p.path as path
+            FROM full_paths p
+            INNER JOIN folders f ON f.id = p.parent AND f.drive_id = p.drive_id
+            INNER JOIN parents l ON l.id = f.id AND l.drive_id = f.drive_id
+        )
+    SELECT p.kind, p.id, p.drive_id, p.deleted, p.trashed, p.path FROM full_paths p
+    WHERE p.parent = p.drive_id;
@@ -0,0 +1,44 @@
+-- Staging area for full synchronisations, so an interrupted synchronisation can resume from the last page.
+CREATE TABLE staging_drives (
+    'id' TEXT PRIMARY KEY NOT NULL,
+    'name' TEXT NOT NULL,
+    -- The start page token captured before the first page, used for the changes after promotion.
+    'page_token' TEXT NOT NULL,
+    -- The token of the next page to fetch, NULL before the first page.
+    'next_page_token' TEXT,
+    -- Whether the last page has been fetched.
+    'complete' BOOLEAN NOT NULL DEFAULT 0
+);
+
+-- The staging tables have no constraints on the parents, as those may arrive on a later page.
+CREATE TABLE staging_folders (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    PRIMARY KEY('id', 'drive_id'),
+    FOREIGN KEY('drive_id') REFERENCES staging_drives('id') ON DELETE CASCADE
+);
+
+CREATE TABLE staging_files (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'kind' TEXT NOT NULL,
+    'mime_type' TEXT NOT NULL,
+    'md5' TEXT,
+    'size' BIGINT,
+    'target_id' TEXT,
+    'target_mime_type' TEXT,
+    PRIMARY KEY('id', 'drive_id'),
+    FOREIGN KEY('drive_id') REFERENCES staging_drives('id') ON DELETE CASCADE
+);
+
+CREATE TABLE staging_parents (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'parent' TEXT NOT NULL,
+    PRIMARY KEY('id', 'drive_id', 'parent'),
+    FOREIGN KEY('drive_id') REFERENCES staging_drives('id') ON DELETE CASCADE
+);
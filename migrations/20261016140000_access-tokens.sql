@@ -0,0 +1,12 @@
+-- Access tokens which are reused across restarts until they expire.
+CREATE TABLE access_tokens (
+    -- The cache key of the token provider, such as the email address of the Service Account.
+    'account' TEXT NOT NULL,
+    -- The URL of the token endpoint which issued the token, as a token is only valid there.
+    'token_url' TEXT NOT NULL,
+    -- The space-separated and sorted OAuth 2.0 scopes.
+    'scope' TEXT NOT NULL,
+    'token' TEXT NOT NULL,
+    'expiry' DATETIME NOT NULL,
+    PRIMARY KEY('account', 'token_url', 'scope')
+);
@@ -0,0 +1,3 @@
+-- Whether a drive is a Shared Drive or the My Drive of the user.
+ALTER TABLE drives ADD COLUMN 'corpus' TEXT NOT NULL DEFAULT 'drive';
+ALTER TABLE staging_drives ADD COLUMN 'corpus' TEXT NOT NULL DEFAULT 'drive';
@@ -0,0 +1,49 @@
+-- Walk up the changed ancestors along the rows of the same state, so the former path of a moved item
+-- is built from the former names of its ancestors and the new path from their new names.
+-- Every row keeps the deleted and trashed state of the item itself, like the `paths` view.
+-- An ancestor without deleted rows, such as a folder which has only gained a parent, is walked
+-- through its current parents, where the former path skips the parents it has gained.
+DROP VIEW path_changelog;
+
+CREATE VIEW path_changelog AS
+    WITH
+        changelog_paths AS (
+            -- Initial folders
+            SELECT 'folder' as 'kind', f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM folder_changelog f
+
+            UNION ALL
+
+            -- Initial files
+            SELECT f.kind, f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path FROM file_changelog f
+
+            UNION ALL
+
+            -- Recursive clause (using p.id to preserve original id)
+            SELECT p.kind, p.id, f.drive_id, f.parent, p.deleted, p.trashed, "/" || f.name || p.path as path
+            FROM folder_changelog f, changelog_paths p
+            WHERE f.id = p.parent AND f.drive_id = p.drive_id AND f.deleted = p.deleted
+        ),
+        full_paths AS (
+            -- Initial changed paths
+            SELECT p.kind, p.id, p.drive_id, p.parent, p.deleted, p.trashed, p.path FROM changelog_paths p
+            -- Not exists to only get the "full" path of each id.
+            WHERE NOT EXISTS (
+                SELECT * FROM changelog_paths p2
+                WHERE p2.id = p.parent AND p2.drive_id = p.drive_id AND p2.deleted = p.deleted
+            )
+
+            UNION ALL
+
+            -- Recursive clause, following every parent of the unchanged ancestors
+            SELECT p.kind, p.id, f.drive_id, l.parent, p.deleted, p.trashed, "/" || f.name || p.path as path
+            FROM full_paths p
+            INNER JOIN folders f ON f.id = p.parent AND f.drive_id = p.drive_id
+            INNER JOIN parents l ON l.id = f.id AND l.drive_id = f.drive_id
+            -- The former path does not go through the parents an ancestor has gained since.
+            WHERE NOT (p.deleted AND EXISTS (
+                SELECT * FROM folder_changelog c
+                WHERE c.id = l.id AND c.drive_id = l.drive_id AND c.parent = l.parent AND NOT c.deleted
+            ))
+        )
+    SELECT p.kind, p.id, p.drive_id, p.deleted, p.trashed, p.path FROM full_paths p
+    WHERE p.parent = p.drive_id;
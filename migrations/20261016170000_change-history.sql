@@ -0,0 +1,117 @@
+-- Every synchronisation which changed any path, numbered in increasing order across all drives.
+-- AUTOINCREMENT never reuses a generation, even after the history has been pruned.
+CREATE TABLE generations (
+    'generation' INTEGER PRIMARY KEY AUTOINCREMENT,
+    'drive_id' TEXT NOT NULL
+);
+
+-- The changelog is kept across synchronisations, where every row belongs to the generation of the
+-- synchronisation which wrote it. The triggers write generation 0, which is replaced by the actual
+-- generation before the synchronisation commits, so the foreign key is only checked on commit.
+-- The rows of the previous synchronisations are dropped along with the changelog.
+DROP VIEW path_changelog;
+DROP TABLE folder_changelog;
+DROP TABLE file_changelog;
+
+CREATE TABLE folder_changelog (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'deleted' BOOLEAN NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'parent' TEXT NOT NULL,
+    'generation' INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY('generation', 'id', 'drive_id', 'deleted', 'parent'),
+    FOREIGN KEY('generation') REFERENCES generations('generation')
+        ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED
+);
+
+CREATE TABLE file_changelog (
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'deleted' BOOLEAN NOT NULL,
+    'name' TEXT NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'parent' TEXT NOT NULL,
+    'kind' TEXT NOT NULL,
+    'mime_type' TEXT NOT NULL,
+    'md5' TEXT,
+    'size' BIGINT,
+    'target_id' TEXT,
+    'target_mime_type' TEXT,
+    'generation' INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY('generation', 'id', 'drive_id', 'deleted', 'parent'),
+    FOREIGN KEY('generation') REFERENCES generations('generation')
+        ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED
+);
+
+-- Same as before, but the rows of a path all belong to the same generation.
+CREATE VIEW path_changelog AS
+    WITH
+        changelog_paths AS (
+            -- Initial folders
+            SELECT 'folder' as 'kind', f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path, f.generation FROM folder_changelog f
+
+            UNION ALL
+
+            -- Initial files
+            SELECT f.kind, f.id, f.drive_id, f.parent, f.deleted, f.trashed, "/" || f.name as path, f.generation FROM file_changelog f
+
+            UNION ALL
+
+            -- Recursive clause (using p.id to preserve original id)
+            SELECT p.kind, p.id, f.drive_id, f.parent, p.deleted, p.trashed, "/" || f.name || p.path as path, p.generation
+            FROM folder_changelog f, changelog_paths p
+            WHERE f.id = p.parent AND f.drive_id = p.drive_id AND f.deleted = p.deleted AND f.generation = p.generation
+        ),
+        full_paths AS (
+            -- Initial changed paths
+            SELECT p.kind, p.id, p.drive_id, p.parent, p.deleted, p.trashed, p.path, p.generation FROM changelog_paths p
+            -- Not exists to only get the "full" path of each id.
+            WHERE NOT EXISTS (
+                SELECT * FROM changelog_paths p2
+                WHERE p2.id = p.parent AND p2.drive_id = p.drive_id AND p2.deleted = p.deleted AND p2.generation = p.generation
+            )
+
+            UNION ALL
+
+            -- Recursive clause, following every parent of the unchanged ancestors
+            SELECT p.kind, p.id, f.drive_id, l.parent, p.deleted, p.trashed, "/" || f.name || p.path as path, p.generation
+            FROM full_paths p
+            INNER JOIN folders f ON f.id = p.parent AND f.drive_id = p.drive_id
+            INNER JOIN parents l ON l.id = f.id AND l.drive_id = f.drive_id
+            -- The former path does not go through the parents an ancestor has gained since.
+            WHERE NOT (p.deleted AND EXISTS (
+                SELECT * FROM folder_changelog c
+                WHERE c.id = l.id AND c.drive_id = l.drive_id AND c.parent = l.parent AND NOT c.deleted
+                AND c.generation = p.generation
+            ))
+        )
+    SELECT p.kind, p.id, p.drive_id, p.deleted, p.trashed, p.path, p.generation FROM full_paths p
+    WHERE p.parent = p.drive_id;
+
+-- The rows of `path_changelog` at the end of every generation.
+-- The paths are resolved against the folders of that time, so they are kept as they were.
+-- The drive is not a foreign key, so the paths of a removed drive remain available.
+CREATE TABLE path_history (
+    'generation' INTEGER NOT NULL,
+    'id' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'kind' TEXT NOT NULL,
+    'deleted' BOOLEAN NOT NULL,
+    'trashed' BOOLEAN NOT NULL,
+    'path' TEXT NOT NULL,
+    FOREIGN KEY('generation') REFERENCES generations('generation') ON DELETE CASCADE
+);
+
+CREATE INDEX path_history_generation ON path_history ('drive_id', 'generation');
+CREATE INDEX folder_changelog_generation ON folder_changelog ('drive_id', 'generation');
+CREATE INDEX file_changelog_generation ON file_changelog ('drive_id', 'generation');
+
+-- The last generation every consumer has acknowledged for a drive.
+CREATE TABLE consumers (
+    'consumer' TEXT NOT NULL,
+    'drive_id' TEXT NOT NULL,
+    'generation' INTEGER NOT NULL,
+    PRIMARY KEY('consumer', 'drive_id')
+);
@@ -0,0 +1,23 @@
+-- Every synchronisation of a drive, including the failed ones.
+CREATE TABLE sync_runs (
+    'id' INTEGER PRIMARY KEY AUTOINCREMENT,
+    'drive_id' TEXT NOT NULL,
+    -- full, partial or removed
+    'kind' TEXT NOT NULL,
+    'started_at' DATETIME NOT NULL,
+    'finished_at' DATETIME NOT NULL,
+    'old_page_token' TEXT,
+    'new_page_token' TEXT,
+    -- The number of changes fetched, or items for a full synchronisation.
+    'changes' INTEGER NOT NULL,
+    'folders_created' INTEGER NOT NULL,
+    'folders_updated' INTEGER NOT NULL,
+    'folders_deleted' INTEGER NOT NULL,
+    'files_created' INTEGER NOT NULL,
+    'files_updated' INTEGER NOT NULL,
+    'files_deleted' INTEGER NOT NULL,
+    -- The error kind of a failed synchronisation, such as network.
+    'error' TEXT
+);
+
+CREATE INDEX sync_runs_drive ON sync_runs ('drive_id');
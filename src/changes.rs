@@ -1,34 +1,78 @@
-use crate::{database, Bernard, ChangedFile, ChangedFolder, ChangedPath, Result};
+use crate::{database, Bernard, ChangedFile, ChangedFolder, ChangedPath, Result, SyncReport};
 
 // Opportunity: Changes could hold the transaction to ensure it reflects the current database state.
 // To make this work, the *actual* transaction would use a savepoint.
 pub struct Changes<'a> {
     bernard: &'a Bernard,
-    drive_id: &'a str,
+    drive_id: String,
+    report: SyncReport,
 }
 
 impl<'a> Changes<'a> {
-    pub(crate) fn new(bernard: &'a Bernard, drive_id: &'a str) -> Self {
-        Self { bernard, drive_id }
+    pub(crate) fn new(bernard: &'a Bernard, drive_id: &str, report: SyncReport) -> Self {
+        Self {
+            bernard,
+            drive_id: drive_id.to_owned(),
+            report,
+        }
     }
 
+    /// What the synchronisation has done.
+    pub fn report(&self) -> &SyncReport {
+        &self.report
+    }
+
+    /// The changed paths, which are also part of [`Bernard::changes_since`] under the
+    /// generation of the report.
     #[tracing::instrument(level = "trace", skip(self), fields(self.drive_id))]
     pub async fn paths(&self) -> Result<Vec<ChangedPath>> {
-        database::get_changed_paths(self.drive_id, &self.bernard.pool)
+        let generation = match self.report.generation {
+            Some(generation) => generation,
+            None => return Ok(Vec::new()),
+        };
+
+        database::get_changed_paths(&self.drive_id, generation, &self.bernard.pool)
             .await
             .map_err(|e| e.into())
     }
 
+    /// Same as [`Changes::paths`], but also reports the descendants of every folder which has
+    /// been moved, renamed, trashed or untrashed, as their paths change with the folder.
+    #[tracing::instrument(level = "trace", skip(self), fields(self.drive_id))]
+    pub async fn paths_with_descendants(&self) -> Result<Vec<ChangedPath>> {
+        let generation = match self.report.generation {
+            Some(generation) => generation,
+            None => return Ok(Vec::new()),
+        };
+
+        database::get_changed_paths_with_descendants(&self.drive_id, generation, &self.bernard.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /// Like the paths, the changed folders and files are retained until every consumer has
+    /// acknowledged them, or until the next synchronisation of a drive without consumers.
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn folders(&self) -> Result<Vec<ChangedFolder>> {
-        database::get_changed_folders(self.drive_id, &self.bernard.pool)
+        let generation = match self.report.generation {
+            Some(generation) => generation,
+            None => return Ok(Vec::new()),
+        };
+
+        database::get_changed_folders(&self.drive_id, generation, &self.bernard.pool)
             .await
             .map_err(|e| e.into())
     }
 
+    /// Retained as long as the folders, see [`Changes::folders`].
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn files(&self) -> Result<Vec<ChangedFile>> {
-        database::get_changed_files(self.drive_id, &self.bernard.pool)
+        let generation = match self.report.generation {
+            Some(generation) => generation,
+            None => return Ok(Vec::new()),
+        };
+
+        database::get_changed_files(&self.drive_id, generation, &self.bernard.pool)
             .await
             .map_err(|e| e.into())
     }
@@ -0,0 +1,242 @@
+use crate::{fetch, Account};
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use jsonwebtoken::{encode, Algorithm, Header};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// The error of a [`TokenProvider`].
+pub type TokenError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An OAuth 2.0 access token for the Google APIs.
+#[derive(Clone, Debug)]
+pub struct AccessToken {
+    pub token: String,
+    pub expiry: DateTime<Utc>,
+}
+
+/// The parameters of a request for a new access token.
+pub struct TokenRequest<'a> {
+    client: &'a Client,
+    token_url: &'a str,
+    scopes: &'a [String],
+    lifetime: Duration,
+}
+
+impl<'a> TokenRequest<'a> {
+    pub(crate) fn new(
+        client: &'a Client,
+        token_url: &'a str,
+        scopes: &'a [String],
+        lifetime: Duration,
+    ) -> Self {
+        Self {
+            client,
+            token_url,
+            scopes,
+            lifetime,
+        }
+    }
+
+    /// The HTTP client of Bernard, including the proxy settings.
+    pub fn client(&self) -> &'a Client {
+        self.client
+    }
+
+    /// The OAuth 2.0 token endpoint, see [`BernardBuilder::token_url`](crate::BernardBuilder::token_url).
+    pub fn token_url(&self) -> &'a str {
+        self.token_url
+    }
+
+    /// The OAuth 2.0 scopes the access token must be valid for.
+    pub fn scopes(&self) -> &'a [String] {
+        self.scopes
+    }
+
+    /// The requested lifetime of the access token.
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+}
+
+/// A source of access tokens for the Google APIs.
+///
+/// Bernard caches the access token until shortly before it expires,
+/// so a new token is only requested when needed.
+pub trait TokenProvider: Send + Sync {
+    fn access_token<'a>(
+        &'a self,
+        request: TokenRequest<'a>,
+    ) -> BoxFuture<'a, Result<AccessToken, TokenError>>;
+
+    /// A stable identifier of the credentials, under which the access token is stored
+    /// when the token cache is enabled, see [`BernardBuilder::cache_tokens`](crate::BernardBuilder::cache_tokens).
+    /// The access token is never stored when `None`, which is the default.
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<&'a str>,
+    scope: String,
+    aud: &'a str,
+
+    #[serde(with = "ts_seconds")]
+    exp: DateTime<Utc>,
+
+    #[serde(with = "ts_seconds")]
+    iat: DateTime<Utc>,
+}
+
+impl<'a> Claims<'a> {
+    fn new(iss: &'a str, sub: Option<&'a str>, request: &TokenRequest<'a>) -> Self {
+        let iat = Utc::now();
+
+        Self {
+            aud: request.token_url,
+            scope: request.scopes.join(" "),
+            exp: iat + request.lifetime,
+            iat,
+            iss,
+            sub,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+/// The Service Account signs a JWT, which is exchanged for an access token.
+impl TokenProvider for Account {
+    fn access_token<'a>(
+        &'a self,
+        request: TokenRequest<'a>,
+    ) -> BoxFuture<'a, Result<AccessToken, TokenError>> {
+        async move {
+            let claims = Claims::new(&self.client_email, self.subject.as_deref(), &request);
+            let jwt = tokio::task::block_in_place(|| {
+                encode(&Header::new(Algorithm::RS256), &claims, &self.private_key.0)
+            })?;
+
+            #[derive(Serialize)]
+            struct Form<'a> {
+                grant_type: &'a str,
+                assertion: &'a str,
+            }
+
+            let form = Form {
+                assertion: &jwt,
+                grant_type: "urn:ietf:params:oauth:grant-type:jwt-bearer",
+            };
+
+            let http_request = request.client.post(request.token_url).form(&form).build()?;
+            let response: TokenResponse = fetch::request_json(request.client, http_request).await?;
+
+            Ok(AccessToken {
+                token: response.access_token,
+                expiry: claims.exp,
+            })
+        }
+        .boxed()
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        match &self.subject {
+            Some(subject) => Some(format!("{} as {}", self.client_email, subject)),
+            None => Some(self.client_email.clone()),
+        }
+    }
+}
+
+/// The credentials of a user of an OAuth 2.0 installed application,
+/// as stored in the `authorized_user` JSON files of the Google Cloud SDK.
+///
+/// The refresh token is exchanged for an access token.
+#[derive(Debug, Deserialize)]
+pub struct AuthorizedUser {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+impl AuthorizedUser {
+    pub fn new<S: Into<String>>(client_id: S, client_secret: S, refresh_token: S) -> Self {
+        Self {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            refresh_token: refresh_token.into(),
+        }
+    }
+}
+
+impl TokenProvider for AuthorizedUser {
+    fn access_token<'a>(
+        &'a self,
+        request: TokenRequest<'a>,
+    ) -> BoxFuture<'a, Result<AccessToken, TokenError>> {
+        async move {
+            #[derive(Serialize)]
+            struct Form<'a> {
+                grant_type: &'a str,
+                client_id: &'a str,
+                client_secret: &'a str,
+                refresh_token: &'a str,
+            }
+
+            let form = Form {
+                grant_type: "refresh_token",
+                client_id: &self.client_id,
+                client_secret: &self.client_secret,
+                refresh_token: &self.refresh_token,
+            };
+
+            let now = Utc::now();
+            let http_request = request.client.post(request.token_url).form(&form).build()?;
+            let response: TokenResponse = fetch::request_json(request.client, http_request).await?;
+
+            // The scopes of the refresh token are fixed when the user grants consent.
+            let lifetime = response
+                .expires_in
+                .map(Duration::seconds)
+                .unwrap_or(request.lifetime);
+
+            Ok(AccessToken {
+                token: response.access_token,
+                expiry: now + lifetime,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// An access token obtained elsewhere, which is used as is and never refreshed.
+#[derive(Debug)]
+pub struct StaticToken(String);
+
+impl StaticToken {
+    pub fn new<S: Into<String>>(token: S) -> Self {
+        Self(token.into())
+    }
+}
+
+impl TokenProvider for StaticToken {
+    fn access_token<'a>(
+        &'a self,
+        _request: TokenRequest<'a>,
+    ) -> BoxFuture<'a, Result<AccessToken, TokenError>> {
+        future::ok(AccessToken {
+            token: self.0.clone(),
+            expiry: chrono::MAX_DATETIME,
+        })
+        .boxed()
+    }
+}
@@ -1,7 +1,13 @@
-use crate::fetch::{Change, Item};
-use crate::model::{ChangedFile, ChangedFolder, ChangedPath, Drive, File, Folder};
+use crate::credentials::AccessToken;
+use crate::fetch::{Change, Item, Page};
+use crate::model::{
+    shared_with_me_id, CachedToken, ChangeSet, ChangedFile, ChangedFolder, ChangedPath, Corpus,
+    Drive, Entry, File, Folder, History, Parents, Path, ShortcutPolicy, StagedDrive, SyncRun,
+    SHARED_WITH_ME_NAME,
+};
+use futures::prelude::*;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use tracing::{error, trace, warn};
 
 pub(crate) type Connection = SqliteConnection;
@@ -21,20 +27,14 @@ pub async fn establish_connection(database_path: &str) -> sqlx::Result<Pool> {
     Ok(pool)
 }
 
-pub async fn clear_changelog(drive_id: &str, pool: &Pool) -> sqlx::Result<()> {
-    ChangedFolder::clear(drive_id, pool).await?;
-    ChangedFile::clear(drive_id, pool).await?;
-
-    Ok(())
-}
-
 #[tracing::instrument(level = "debug", skip(changes, pool))]
 pub async fn merge_changes<I>(
     drive_id: &str,
+    corpus: Corpus,
     changes: I,
     page_token: &str,
     pool: &Pool,
-) -> sqlx::Result<()>
+) -> sqlx::Result<Option<i64>>
 where
     I: IntoIterator<Item = Change>,
 {
@@ -52,7 +52,10 @@ where
             Change::DriveChanged(drive) => {
                 Folder::update_name(drive_id, drive_id, &drive.name, &mut tx).await?;
             }
-            Change::DriveRemoved(_) => (), // Ignore, as we're processing this drive
+            Change::DriveRemoved(id) => {
+                // Ignore, the removal of the drive itself is handled by `remove_drive`.
+                trace!(%id, "ignoring drive removal");
+            }
             Change::ItemChanged(item) => {
                 // Integrate item_to_change logic
                 if item.drive_id() == drive_id {
@@ -61,29 +64,41 @@ where
                             folder_changes.insert(folder.id.clone(), FolderChange::Update(folder));
                         }
                         Item::File(file) => {
-                            file_changes.insert(file.id.clone(), FileChange::Update(file));
+                            file_changes
+                                .insert(file.id.clone(), FileChange::Update(Box::new(file)));
                         }
                     }
                 } else {
                     trace!("moved to another shared drive, marked as removed");
                     let id = item.into_id();
-                    if !file_changes.contains_key(&id) {
-                        folder_changes.insert(id.clone(), FolderChange::Remove);
-                    } else {
-                        file_changes.insert(id, FileChange::Remove);
-                    }
-                }
-            }
-            Change::ItemRemoved(id) => {
-                if !file_changes.contains_key(&id) {
                     folder_changes.insert(id.clone(), FolderChange::Remove);
-                } else {
                     file_changes.insert(id, FileChange::Remove);
                 }
             }
+            Change::ItemRemoved(id) => {
+                // A removal does not tell whether the item was a file or a folder.
+                folder_changes.insert(id.clone(), FolderChange::Remove);
+                file_changes.insert(id, FileChange::Remove);
+            }
         }
     }
 
+    // Items shared with the user may be moved into a folder outside of My Drive.
+    let updated: Vec<String> = match corpus {
+        Corpus::Drive => Vec::new(),
+        Corpus::User => folder_changes
+            .iter()
+            .filter(|(_, change)| matches!(change, FolderChange::Update(_)))
+            .map(|(id, _)| id.clone())
+            .chain(
+                file_changes
+                    .iter()
+                    .filter(|(_, change)| matches!(change, FileChange::Update(_)))
+                    .map(|(id, _)| id.clone()),
+            )
+            .collect(),
+    };
+
     // Process folder changes
     let mut folders_to_delete = Vec::new();
     for (folder_id, change) in folder_changes {
@@ -109,23 +124,27 @@ where
         }
     }
 
-    // Delete Folder (starting from the leaf nodes) 
-    while !folders_to_delete.is_empty() {
-        let mut next_folders_to_delete = Vec::new();
-        for folder_id in folders_to_delete {
-            match Folder::get_children(&folder_id, drive_id, &mut tx).await? {
-                Some(children) if !children.is_empty() => {
-                    next_folders_to_delete.push(folder_id);
-                }
-                _ => {
-                    Folder::delete(&folder_id, drive_id, &mut tx).await?;
-                }
-            }
-        }
-        folders_to_delete = next_folders_to_delete;
+    // Delete the folders, which unlinks their children
+    for folder_id in folders_to_delete {
+        Folder::delete(&folder_id, drive_id, &mut tx).await?;
     }
 
-    tx.commit().await
+    // Parents outside of the drive are only expected for the items shared with the user.
+    // Otherwise the parent is missing from the change list and the deferred constraint fails the commit.
+    if corpus == Corpus::User {
+        let shared_with_me = shared_with_me_id(drive_id);
+        Parents::adopt_dangling(drive_id, &shared_with_me, Some(&updated), &mut tx).await?;
+        Parents::unlink_dangling(drive_id, &mut tx).await?;
+    }
+
+    // Delete the descendants of the deleted folders
+    remove_orphans(drive_id, &mut tx).await?;
+
+    let generation = History::record(drive_id, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(generation)
 }
 
 enum FolderChange {
@@ -134,112 +153,384 @@ enum FolderChange {
 }
 
 enum FileChange {
-    Update(File),
+    Update(Box<File>),
     Remove, // (id, drive_id)
 }
 
-#[tracing::instrument(level = "debug", skip(name, items, pool))]
-pub async fn add_drive(
+/// Remove the drive together with all of its folders and files.
+/// The triggers mark every path of the drive as deleted in the changelog.
+///
+/// Returns the generation of the changes, if the drive had any paths.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn remove_drive(drive_id: &str, pool: &Pool) -> sqlx::Result<Option<i64>> {
+    let mut tx = pool.begin().await?;
+
+    Drive::delete(drive_id, &mut tx).await?;
+    StagedDrive::delete(drive_id, &mut tx).await?;
+    let generation = History::record(drive_id, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(generation)
+}
+
+pub async fn get_staged_drive(drive_id: &str, pool: &Pool) -> sqlx::Result<Option<StagedDrive>> {
+    StagedDrive::get_by_id(drive_id, pool).await
+}
+
+pub async fn get_staged_drives(pool: &Pool) -> sqlx::Result<Vec<StagedDrive>> {
+    StagedDrive::get_all(pool).await
+}
+
+pub async fn count_staged_items(drive_id: &str, pool: &Pool) -> sqlx::Result<i64> {
+    StagedDrive::count_items(drive_id, pool).await
+}
+
+/// Abandon the full synchronisation of a drive, removing every staged page.
+#[tracing::instrument(level = "debug", skip(pool))]
+pub async fn unstage_drive(drive_id: &str, pool: &Pool) -> sqlx::Result<()> {
+    let mut conn = pool.acquire().await?;
+
+    StagedDrive::delete(drive_id, &mut conn).await
+}
+
+/// Start the full synchronisation of a drive within the staging tables.
+#[tracing::instrument(level = "debug", skip(name, pool))]
+pub async fn stage_drive(
     drive_id: &str,
     name: &str,
     page_token: &str,
-    items: impl IntoIterator<Item = Item>,
+    corpus: Corpus,
     pool: &Pool,
 ) -> sqlx::Result<()> {
+    let mut conn = pool.acquire().await?;
+
+    StagedDrive::create(drive_id, name, page_token, corpus, &mut conn).await
+}
+
+/// Stage a page of items together with the token of the next page, so the listing can resume after it.
+#[tracing::instrument(level = "debug", skip(page, pool))]
+pub async fn stage_page(drive_id: &str, page: Page, pool: &Pool) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    trace!(count = page.items.len(), "staging page");
+
+    for item in &page.items {
+        match item {
+            Item::Folder(folder) => StagedDrive::add_folder(folder, &mut tx).await?,
+            Item::File(file) => StagedDrive::add_file(file, &mut tx).await?,
+        }
+    }
+
+    StagedDrive::update_next_page_token(drive_id, page.next_page_token.as_deref(), &mut tx)
+        .await?;
+
+    tx.commit().await
+}
+
+/// Create the drive from its staged items in a single transaction.
+///
+/// Returns the IDs of the orphaned folders and files, which have been skipped,
+/// and the generation of the changes.
+#[tracing::instrument(level = "debug", skip(staged, pool), fields(drive_id = %staged.id))]
+pub async fn promote_drive(
+    staged: &StagedDrive,
+    pool: &Pool,
+) -> sqlx::Result<(Vec<String>, Vec<String>, Option<i64>)> {
+    let drive_id = staged.id.as_str();
     let mut tx = pool.begin().await?;
 
     // Create the drive
-    Drive::create(drive_id, page_token, &mut tx).await?;
+    Drive::create(drive_id, &staged.page_token, staged.corpus, &mut tx).await?;
 
     // Create the root folder
     let root_folder = Folder {
         id: drive_id.to_owned(),
         drive_id: drive_id.to_owned(),
-        name: name.to_owned(),
-        parent: None,
+        name: staged.name.clone(),
+        parents: Vec::new(),
         trashed: false,
     };
     root_folder.create(&mut tx).await?;
 
-    // Separate folders and files, and create maps
-    let mut folders: HashMap<String, Folder> = HashMap::new();
-    let mut files: Vec<File> = Vec::new();
+    // The parents are checked at the end of the transaction,
+    // so items can be created regardless of the order of their parents.
+    StagedDrive::promote(drive_id, &mut tx).await?;
+
+    // The items shared with the user whose parents are not part of My Drive.
+    if staged.corpus == Corpus::User {
+        let shared_with_me = Folder {
+            id: shared_with_me_id(drive_id),
+            drive_id: drive_id.to_owned(),
+            name: SHARED_WITH_ME_NAME.to_owned(),
+            parents: vec![drive_id.to_owned()],
+            trashed: false,
+        };
+        shared_with_me.create(&mut tx).await?;
+
+        Parents::adopt_dangling(drive_id, &shared_with_me.id, None, &mut tx).await?;
+    }
 
-    for item in items {
-        match item {
-            Item::Folder(folder) => {
-                folders.insert(folder.id.clone(), folder);
-            }
-            Item::File(file) => {
-                files.push(file);
-            }
-        }
+    // The listing of the drive is complete, so parents which are not part of it are skipped.
+    Parents::unlink_dangling(drive_id, &mut tx).await?;
+    let (orphaned_folders, orphaned_files) = remove_orphans(drive_id, &mut tx).await?;
+
+    if !orphaned_folders.is_empty() {
+        error!("Orphaned folders detected: {:?}", orphaned_folders);
+        warn!("Continuing processing despite orphaned folders");
     }
 
-    // Process folders
-    let mut processed_folders: HashSet<String> = HashSet::new();
-    processed_folders.insert(drive_id.to_string());
+    for file_id in &orphaned_files {
+        warn!("Parent folder not found for file {}, skipping", file_id);
+    }
 
-    let mut orphaned_folders = Vec::new();
+    let generation = History::record(drive_id, &mut tx).await?;
 
-    while !folders.is_empty() {
-        let mut progress = false;
-        let mut folders_to_remove = Vec::new();
+    // Commit the transaction
+    tx.commit().await?;
 
-        for (id, folder) in &folders {
-            if processed_folders.contains(folder.parent.as_ref().unwrap_or(&drive_id.to_string())) {
-                folder.create(&mut tx).await?;
-                processed_folders.insert(id.clone());
-                folders_to_remove.push(id.clone());
-                progress = true;
-            }
-        }
+    Ok((orphaned_folders, orphaned_files, generation))
+}
 
-        // Remove processed folders
-        for id in &folders_to_remove {
-            folders.remove(id);
+/// Remove every folder and file without any parent, which is no longer reachable from the root folder.
+///
+/// Returns the IDs of the removed folders and files.
+async fn remove_orphans(
+    drive_id: &str,
+    conn: &mut Connection,
+) -> sqlx::Result<(Vec<String>, Vec<String>)> {
+    // Removing a folder unlinks its children, so repeat until no orphans are left.
+    let mut orphaned_folders = Vec::new();
+    loop {
+        let orphans = Folder::get_orphans(drive_id, conn).await?;
+        if orphans.is_empty() {
+            break;
         }
 
-        // If no progress, remaining folders are orphaned
-        if !progress {
-            orphaned_folders.extend(folders.keys().cloned());
-            break;
+        for folder_id in &orphans {
+            Folder::delete(folder_id, drive_id, conn).await?;
         }
-    }
 
-    if !orphaned_folders.is_empty() {
-        error!("Orphaned folders detected: {:?}", orphaned_folders);
-        warn!("Continuing processing despite orphaned folders");
+        orphaned_folders.extend(orphans);
     }
 
-    // Process files
-    for file in files {
-        if processed_folders.contains(&file.parent) {
-            file.create(&mut tx).await?;
-        } else {
-            warn!(
-                "Parent folder {} not found for file {}, skipping",
-                file.parent, file.id
-            );
-        }
+    let orphaned_files = File::get_orphans(drive_id, conn).await?;
+    for file_id in &orphaned_files {
+        File::delete(file_id, drive_id, conn).await?;
     }
 
-    // Commit the transaction
-    tx.commit().await
+    Ok((orphaned_folders, orphaned_files))
+}
+
+pub async fn get_access_token(
+    account: &str,
+    token_url: &str,
+    scope: &str,
+    pool: &Pool,
+) -> sqlx::Result<Option<AccessToken>> {
+    CachedToken::get(account, token_url, scope, pool).await
+}
+
+pub async fn save_access_token(
+    account: &str,
+    token_url: &str,
+    scope: &str,
+    token: &AccessToken,
+    pool: &Pool,
+) -> sqlx::Result<()> {
+    CachedToken::upsert(account, token_url, scope, token, pool).await
+}
+
+pub async fn remove_access_token(
+    account: &str,
+    token_url: &str,
+    scope: &str,
+    token: &str,
+    pool: &Pool,
+) -> sqlx::Result<()> {
+    CachedToken::delete(account, token_url, scope, token, pool).await
 }
 
 pub async fn get_drive(drive_id: &str, pool: &Pool) -> sqlx::Result<Option<Drive>> {
     Drive::get_by_id(drive_id, pool).await
 }
 
-pub async fn get_changed_files(drive_id: &str, pool: &Pool) -> sqlx::Result<Vec<ChangedFile>> {
-    ChangedFile::get_all(drive_id, pool).await
+pub async fn get_drives(pool: &Pool) -> sqlx::Result<Vec<Drive>> {
+    Drive::get_all(pool).await
 }
 
-pub async fn get_changed_folders(drive_id: &str, pool: &Pool) -> sqlx::Result<Vec<ChangedFolder>> {
-    ChangedFolder::get_all(drive_id, pool).await
+pub async fn get_changed_files(
+    drive_id: &str,
+    generation: i64,
+    pool: &Pool,
+) -> sqlx::Result<Vec<ChangedFile>> {
+    ChangedFile::get_all(drive_id, generation, pool).await
 }
 
-pub async fn get_changed_paths(drive_id: &str, pool: &Pool) -> sqlx::Result<Vec<ChangedPath>> {
-    ChangedPath::get_all(drive_id, pool).await
+pub async fn get_changed_folders(
+    drive_id: &str,
+    generation: i64,
+    pool: &Pool,
+) -> sqlx::Result<Vec<ChangedFolder>> {
+    ChangedFolder::get_all(drive_id, generation, pool).await
+}
+
+pub async fn get_changed_paths(
+    drive_id: &str,
+    generation: i64,
+    pool: &Pool,
+) -> sqlx::Result<Vec<ChangedPath>> {
+    let mut conn = pool.acquire().await?;
+    ChangedPath::get_all(drive_id, generation, &mut conn).await
+}
+
+pub async fn get_changed_paths_with_descendants(
+    drive_id: &str,
+    generation: i64,
+    pool: &Pool,
+) -> sqlx::Result<Vec<ChangedPath>> {
+    // A single read transaction, so the descendants match the changelog.
+    let mut tx = pool.begin().await?;
+    let paths = ChangedPath::get_all_with_descendants(drive_id, generation, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(paths)
+}
+
+pub async fn get_changes_since(
+    drive_id: &str,
+    generation: i64,
+    pool: &Pool,
+) -> sqlx::Result<Vec<ChangeSet>> {
+    History::get_since(drive_id, generation, pool).await
+}
+
+pub async fn get_cursor(consumer: &str, drive_id: &str, pool: &Pool) -> sqlx::Result<Option<i64>> {
+    History::get_cursor(consumer, drive_id, pool).await
+}
+
+pub async fn register_consumer(consumer: &str, drive_id: &str, pool: &Pool) -> sqlx::Result<()> {
+    History::register(consumer, drive_id, pool).await
+}
+
+pub async fn acknowledge(
+    consumer: &str,
+    drive_id: &str,
+    generation: i64,
+    pool: &Pool,
+) -> sqlx::Result<()> {
+    History::acknowledge(consumer, drive_id, generation, pool).await
+}
+
+pub async fn remove_consumer(consumer: &str, pool: &Pool) -> sqlx::Result<()> {
+    History::remove_consumer(consumer, pool).await
+}
+
+pub async fn prune_history(drive_id: &str, pool: &Pool) -> sqlx::Result<u64> {
+    History::prune(drive_id, pool).await
+}
+
+/// Store the synchronisation, where a successful one counts its changes from the changelog.
+pub async fn record_sync_run(
+    run: &mut SyncRun,
+    generation: Option<i64>,
+    pool: &Pool,
+) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    if let (true, Some(generation)) = (run.succeeded(), generation) {
+        run.count_changelog(generation, &mut tx).await?;
+    }
+    run.create(&mut tx).await?;
+
+    tx.commit().await
+}
+
+pub async fn get_sync_runs(drive_id: &str, pool: &Pool) -> sqlx::Result<Vec<SyncRun>> {
+    SyncRun::get_all(drive_id, pool).await
+}
+
+pub async fn get_paths(
+    drive_id: &str,
+    policy: ShortcutPolicy,
+    pool: &Pool,
+) -> sqlx::Result<Vec<Path>> {
+    Path::get_all(drive_id, policy, pool).await
+}
+
+pub fn stream_paths<'a>(
+    drive_id: &'a str,
+    policy: ShortcutPolicy,
+    pool: &'a Pool,
+) -> impl Stream<Item = sqlx::Result<Path>> + 'a {
+    Path::stream_all(drive_id, policy, pool)
+}
+
+pub async fn get_paths_of(id: &str, drive_id: &str, pool: &Pool) -> sqlx::Result<Vec<Path>> {
+    Path::get_by_id(id, drive_id, pool).await
+}
+
+pub async fn get_entry(id: &str, drive_id: &str, pool: &Pool) -> sqlx::Result<Option<Entry>> {
+    if let Some(file) = File::get_by_id(id, drive_id, pool).await? {
+        return Ok(Some(Entry::File(file)));
+    }
+
+    Ok(Folder::get_by_id(id, drive_id, pool).await?.map(Entry::Folder))
+}
+
+pub async fn get_children(
+    folder_id: &str,
+    drive_id: &str,
+    pool: &Pool,
+) -> sqlx::Result<Vec<Entry>> {
+    get_children_by_name(folder_id, drive_id, None, pool).await
+}
+
+async fn get_children_by_name(
+    folder_id: &str,
+    drive_id: &str,
+    name: Option<&str>,
+    pool: &Pool,
+) -> sqlx::Result<Vec<Entry>> {
+    let folders = Folder::get_by_parent(folder_id, drive_id, name, pool).await?;
+    let files = File::get_by_parent(folder_id, drive_id, name, pool).await?;
+
+    Ok(folders
+        .into_iter()
+        .map(Entry::Folder)
+        .chain(files.into_iter().map(Entry::File))
+        .collect())
+}
+
+/// Walk down from the root folder of the drive, one path component at a time.
+/// Google Drive allows multiple items with the same name, so every match is followed.
+pub async fn lookup(drive_id: &str, path: &str, pool: &Pool) -> sqlx::Result<Vec<Entry>> {
+    let mut components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+
+    // The root folder has the ID of the drive.
+    let name = match components.pop() {
+        Some(name) => name,
+        None => {
+            let root = Folder::get_by_id(drive_id, drive_id, pool).await?;
+            return Ok(root.into_iter().map(Entry::Folder).collect());
+        }
+    };
+
+    let mut parents = vec![drive_id.to_owned()];
+    for component in components {
+        let mut next_parents = Vec::new();
+        for parent in &parents {
+            let folders =
+                Folder::get_by_parent(parent, drive_id, Some(component), pool).await?;
+            next_parents.extend(folders.into_iter().map(|folder| folder.id));
+        }
+        parents = next_parents;
+    }
+
+    let mut entries = Vec::new();
+    for parent in &parents {
+        entries.extend(get_children_by_name(parent, drive_id, Some(name), pool).await?);
+    }
+
+    Ok(entries)
 }
@@ -1,124 +1,101 @@
-use crate::{fetch, Account};
-use chrono::serde::ts_seconds;
-use chrono::{DateTime, Duration, Utc};
-use itertools::join;
-use jsonwebtoken::{encode, Algorithm, Header};
-use serde::{Deserialize, Serialize};
+use crate::credentials::{AccessToken, TokenProvider, TokenRequest};
+use crate::database;
+use chrono::{Duration, Utc};
 use std::collections::HashSet;
-use std::sync::Arc;
 use tokio::sync::Mutex;
+use tracing::warn;
 
-use super::{Fetcher, Result};
+use super::{Error, Fetcher, Result};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims<'a> {
-    iss: &'a str,
-    scope: String,
-    aud: &'a str,
-
-    #[serde(with = "ts_seconds")]
-    exp: DateTime<Utc>,
-
-    #[serde(with = "ts_seconds")]
-    iat: DateTime<Utc>,
+/// Caches the access token of the token provider until shortly before it expires.
+pub(crate) struct RefreshToken {
+    provider: Box<dyn TokenProvider>,
+    scope: Scope,
+    token: Mutex<Option<AccessToken>>,
 }
 
-impl<'a> Claims<'a> {
-    fn new(iss: &'a str, scope: &Scope) -> Self {
-        let iat = Utc::now();
-
+impl RefreshToken {
+    pub(crate) fn new(provider: Box<dyn TokenProvider>, scope: Scope) -> Self {
         Self {
-            aud: "https://oauth2.googleapis.com/token",
-            scope: join(&scope.scopes, " "),
-            exp: iat + scope.lifetime,
-            iat,
-            iss,
+            token: Mutex::new(None),
+            provider,
+            scope,
         }
     }
-}
-
-fn create_jwt(account: &Account, scope: &Scope) -> (String, DateTime<Utc>) {
-    let header = Header::new(Algorithm::RS256);
-    let claims = Claims::new(&account.client_email, scope);
 
-    let jwt = encode(&header, &claims, &account.private_key.0).unwrap();
-    (jwt, claims.exp)
-}
+    pub(crate) async fn access_token(&self, fetch: &Fetcher) -> Result<AccessToken> {
+        let mut token_guard = self.token.lock().await;
 
-impl Fetcher {
-    async fn access_token_inner(self: Arc<Fetcher>, scope: &Scope) -> fetch::Result<AccessToken> {
-        let (jwt, exp) = tokio::task::block_in_place(|| create_jwt(&self.account, scope));
+        // Pretend that we are 10 seconds in the future to prevent possible errors.
+        let now = Utc::now() + Duration::seconds(10);
 
-        #[derive(Serialize)]
-        struct Form<'a> {
-            grant_type: &'a str,
-            assertion: &'a str,
+        if let Some(token) = token_guard.as_ref().filter(|token| token.expiry > now) {
+            return Ok(token.clone());
         }
 
-        let form = Form {
-            assertion: &jwt,
-            grant_type: "urn:ietf:params:oauth:grant-type:jwt-bearer",
-        };
-
-        #[derive(Deserialize)]
-        struct Response {
-            access_token: String,
+        let scope = self.scope.key();
+        let cache = fetch.token_cache.as_ref().zip(self.provider.cache_key());
+
+        // Reuse the token of a previous process, if it has not expired yet.
+        if let Some((pool, account)) = &cache {
+            match database::get_access_token(account, &fetch.token_url, &scope, pool).await {
+                Ok(Some(token)) if token.expiry > now => {
+                    *token_guard = Some(token.clone());
+                    return Ok(token);
+                }
+                Ok(_) => {}
+                Err(error) => warn!(%error, "unable to read the cached access token"),
+            }
         }
 
-        let request = self
-            .client
-            .post("https://oauth2.googleapis.com/token")
-            .form(&form)
-            .build()
-            .unwrap();
-
-        let Response { access_token } = self.make_request_inner(request).await?;
-
-        Ok(AccessToken {
-            token: access_token,
-            expiry: exp,
-        })
-    }
-}
-
-impl Account {}
-
-#[derive(Clone, Debug)]
-pub(crate) struct AccessToken {
-    pub expiry: DateTime<Utc>,
-    pub token: String,
-}
-
-pub(crate) struct RefreshToken {
-    scope: Scope,
-    token: Mutex<Option<AccessToken>>,
-}
-
-impl RefreshToken {
-    pub(crate) fn new(scope: Scope) -> Self {
-        Self {
-            token: Mutex::new(None),
-            scope,
+        let scopes: Vec<String> = self.scope.scopes.iter().cloned().collect();
+        let request = TokenRequest::new(
+            &fetch.client,
+            &fetch.token_url,
+            &scopes,
+            self.scope.lifetime,
+        );
+
+        let token = self
+            .provider
+            .access_token(request)
+            .await
+            .map_err(Error::from_token_error)?;
+
+        if let Some((pool, account)) = &cache {
+            let saved =
+                database::save_access_token(account, &fetch.token_url, &scope, &token, pool).await;
+            if let Err(error) = saved {
+                warn!(%error, "unable to cache the access token");
+            }
         }
+
+        *token_guard = Some(token.clone());
+        Ok(token)
     }
 
-    pub(crate) async fn access_token(&self, fetch: Arc<Fetcher>) -> Result<AccessToken> {
+    /// Forget the token after it has been rejected, so the next request obtains a new token.
+    pub(crate) async fn invalidate(&self, token: &str, fetch: &Fetcher) {
         let mut token_guard = self.token.lock().await;
 
-        // Pretend that we are 10 seconds in the future to prevent possible errors.
-        let now = Utc::now() + Duration::seconds(10);
+        // Another request may already have obtained a new token.
+        if token_guard.as_ref().map(|cached| cached.token.as_str()) == Some(token) {
+            *token_guard = None;
+        }
 
-        match token_guard.as_ref() {
-            Some(token) if token.expiry > now => Ok(token.clone()),
-            _ => {
-                let token = fetch.access_token_inner(&self.scope).await?;
-                *token_guard = Some(token.clone());
-                Ok(token)
+        if let Some((pool, account)) = fetch.token_cache.as_ref().zip(self.provider.cache_key()) {
+            let scope = self.scope.key();
+            let removed =
+                database::remove_access_token(&account, &fetch.token_url, &scope, token, pool)
+                    .await;
+            if let Err(error) = removed {
+                warn!(%error, "unable to remove the cached access token");
             }
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Scope {
     lifetime: Duration,
     scopes: HashSet<String>,
@@ -128,6 +105,13 @@ impl Scope {
     pub fn builder() -> ScopeBuilder {
         ScopeBuilder(Self::default())
     }
+
+    /// The sorted scopes separated by spaces, which identify the scope in the token cache.
+    fn key(&self) -> String {
+        let mut scopes: Vec<&str> = self.scopes.iter().map(String::as_str).collect();
+        scopes.sort_unstable();
+        scopes.join(" ")
+    }
 }
 
 impl Default for Scope {
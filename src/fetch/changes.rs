@@ -1,17 +1,20 @@
-use super::{Change, Fetcher, Result};
+use super::{Change, Corpus, Fetcher, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 impl Fetcher {
+    /// All changes of the drive since the page token, together with the next start page token.
+    /// The changes of My Drive are fetched from the changes of the user.
     pub async fn changes(
         self: Arc<Fetcher>,
         drive_id: &str,
+        corpus: Corpus,
         page_token: &str,
     ) -> Result<(Vec<Change>, String)> {
         #[derive(Serialize)]
         #[serde(rename_all = "camelCase")]
         struct Query<'a> {
-            drive_id: &'a str,
+            drive_id: Option<&'a str>,
             page_token: &'a str,
 
             fields: &'a str,
@@ -30,6 +33,11 @@ impl Fetcher {
             new_start_page_token: Option<String>,
         }
 
+        let (query_drive_id, all_drives) = match corpus {
+            Corpus::Drive => (Some(drive_id), true),
+            Corpus::User => (None, false),
+        };
+
         let mut all_changes: Vec<Change> = Vec::new();
         let mut page_token = page_token.to_string();
 
@@ -37,24 +45,30 @@ impl Fetcher {
             let fetch = self.clone();
 
             let query = Query {
-                drive_id,
+                drive_id: query_drive_id,
                 page_token: &page_token,
 
-                fields: "nextPageToken,newStartPageToken,changes(driveId,fileId,removed,drive(id,name),file(id,driveId,name,parents,md5Checksum,size,trashed))",
+                fields: "nextPageToken,newStartPageToken,changes(driveId,fileId,removed,drive(id,name),file(id,driveId,name,mimeType,parents,md5Checksum,size,trashed,shortcutDetails(targetId,targetMimeType)))",
                 page_size: 1000,
 
-                all_drives: true,
+                all_drives,
                 supports_all_drives: true,
             };
 
             let request = fetch
                 .client
-                .get("https://www.googleapis.com/drive/v3/changes")
+                .get(fetch.api_url("/changes"))
                 .query(&query);
 
             let response: Response = fetch.with_retry(request).await?;
 
-            all_changes.extend(response.changes);
+            all_changes.extend(response.changes.into_iter().map(|change| match change {
+                Change::ItemChanged(mut item) if corpus == Corpus::User => {
+                    item.assign_to_drive(drive_id);
+                    Change::ItemChanged(item)
+                }
+                change => change,
+            }));
 
             if let Some(next_page_token) = response.next_page_token {
                 page_token = next_page_token;
@@ -1,13 +1,29 @@
-use super::{Fetcher, Item, Result};
+use super::{Corpus, Fetcher, Item, Result};
+use futures::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// A single page of the listing of a drive.
+#[derive(Debug)]
+pub struct Page {
+    pub items: Vec<Item>,
+    /// The token of the next page, or `None` for the last page.
+    pub next_page_token: Option<String>,
+}
+
 impl Fetcher {
-    pub async fn all_files(self: Arc<Fetcher>, drive_id: &str) -> Result<Vec<Item>> {
+    /// Stream all items of the drive, one page at a time, starting at the provided page
+    /// or at the first page. The next page is only requested once the previous page has been consumed.
+    pub fn all_files(
+        self: Arc<Fetcher>,
+        drive_id: &str,
+        corpus: Corpus,
+        page_token: Option<String>,
+    ) -> impl Stream<Item = Result<Page>> {
         #[derive(Serialize)]
         #[serde(rename_all = "camelCase")]
         struct Query<'a> {
-            drive_id: &'a str,
+            drive_id: Option<&'a str>,
             page_token: Option<String>,
 
             fields: &'a str,
@@ -27,37 +43,70 @@ impl Fetcher {
             next_page_token: Option<String>,
         }
 
-        let mut all_items: Vec<Item> = Vec::new();
-        let mut page_token = None;
+        let drive_id = drive_id.to_owned();
 
-        loop {
+        // The state is `None` once the last page has been fetched.
+        stream::try_unfold(Some(page_token), move |page_token: Option<Option<String>>| {
             let fetch = self.clone();
+            let drive_id = drive_id.clone();
+
+            async move {
+                let page_token = match page_token {
+                    Some(page_token) => page_token,
+                    None => return Ok(None),
+                };
+
+                // My Drive is listed without a drive ID and without the items of Shared Drives.
+                let (query_drive_id, corpora, all_drives) = match corpus {
+                    Corpus::Drive => (Some(drive_id.as_str()), "drive", true),
+                    Corpus::User => (None, "user", false),
+                };
 
-            let query = Query {
-                drive_id,
-                page_token,
+                let query = Query {
+                    drive_id: query_drive_id,
+                    page_token,
 
-                fields: "nextPageToken,files(id,driveId,name,parents,md5Checksum,size,trashed)",
-                page_size: 1000,
+                    fields: "nextPageToken,files(id,driveId,name,mimeType,parents,md5Checksum,size,trashed,shortcutDetails(targetId,targetMimeType))",
+                    page_size: 1000,
 
-                corpora: "drive",
-                all_drives: true,
-                supports_all_drives: true,
-            };
+                    corpora,
+                    all_drives,
+                    supports_all_drives: true,
+                };
 
-            let request = fetch
-                .client
-                .get("https://www.googleapis.com/drive/v3/files")
-                .query(&query);
+                let request = fetch
+                    .client
+                    .get(fetch.api_url("/files"))
+                    .query(&query);
 
-            let response: Response = fetch.with_retry(request).await?;
+                let response: Response = fetch.clone().with_retry(request).await?;
 
-            all_items.extend(response.items);
-            page_token = response.next_page_token;
+                let items = match corpus {
+                    Corpus::Drive => response.items,
+                    Corpus::User => assign_to_my_drive(response.items, &drive_id),
+                };
 
-            if page_token.is_none() {
-                return Ok(all_items);
+                let page = Page {
+                    items,
+                    next_page_token: response.next_page_token,
+                };
+                let next_state = page.next_page_token.clone().map(Some);
+
+                Ok(Some((page, next_state)))
             }
-        }
+        })
     }
 }
+
+/// Assign the items of My Drive and the items shared with the user to the drive,
+/// skipping any items of Shared Drives.
+fn assign_to_my_drive(items: Vec<Item>, drive_id: &str) -> Vec<Item> {
+    items
+        .into_iter()
+        .filter(|item| item.drive_id().is_empty())
+        .map(|mut item| {
+            item.assign_to_drive(drive_id);
+            item
+        })
+        .collect()
+}
@@ -1,4 +1,4 @@
-use super::{Fetcher, Result};
+use super::{Fetcher, PartialDrive, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -20,14 +20,76 @@ impl Fetcher {
 
         let request = self
             .client
-            .get(format!(
-                "https://www.googleapis.com/drive/v3/drives/{}",
-                drive_id
-            ))
+            .get(self.api_url(&format!("/drives/{}", drive_id)))
             .query(&query);
 
         let Response { name } = self.with_retry(request).await?;
 
         Ok(name)
     }
+
+    /// The ID and name of the root folder of My Drive.
+    pub async fn my_drive(self: Arc<Fetcher>) -> Result<PartialDrive> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Query<'a> {
+            fields: &'a str,
+        }
+
+        let query = Query { fields: "id,name" };
+
+        let request = self
+            .client
+            .get(self.api_url("/files/root"))
+            .query(&query);
+
+        self.with_retry(request).await
+    }
+
+    /// All Shared Drives the Service Account has access to.
+    pub async fn list_drives(self: Arc<Fetcher>) -> Result<Vec<PartialDrive>> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Query<'a> {
+            page_token: Option<String>,
+
+            fields: &'a str,
+            page_size: usize,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Response {
+            drives: Vec<PartialDrive>,
+            next_page_token: Option<String>,
+        }
+
+        let mut all_drives: Vec<PartialDrive> = Vec::new();
+        let mut page_token = None;
+
+        loop {
+            let fetch = self.clone();
+
+            let query = Query {
+                page_token,
+
+                fields: "nextPageToken,drives(id,name)",
+                page_size: 100,
+            };
+
+            let request = fetch
+                .client
+                .get(fetch.api_url("/drives"))
+                .query(&query);
+
+            let response: Response = fetch.with_retry(request).await?;
+
+            all_drives.extend(response.drives);
+            page_token = response.next_page_token;
+
+            if page_token.is_none() {
+                return Ok(all_drives);
+            }
+        }
+    }
 }
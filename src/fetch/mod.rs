@@ -1,13 +1,15 @@
-use crate::model::{File, Folder};
-use crate::Account;
-use auth::{AccessToken, RefreshToken, Scope};
+use crate::credentials::{AccessToken, TokenError, TokenProvider};
+use crate::database::Pool;
+use crate::model::{shared_with_me_id, Corpus, File, FileKind, Folder};
+use auth::Scope;
+use pool::AccountPool;
 use chrono::Duration;
 use reqwest::{Client, ClientBuilder, IntoUrl, StatusCode};
 use serde::de::Deserializer;
 use serde::Deserialize;
 use snafu::{Backtrace, ResultExt, Snafu};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tap::prelude::*;
 use tracing::{error, trace, warn, Instrument};
 
 mod auth;
@@ -15,19 +17,38 @@ mod changes;
 mod content;
 mod drive;
 mod page_token;
+mod pool;
+mod retry;
+mod stats;
+
+pub use content::Page;
+pub use retry::{Retry, RetryPolicy};
+pub(crate) use retry::RetryHook;
+use retry::NextDelay;
+pub(crate) use stats::{track_requests, RequestStats};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Google Drive API is not enabled"))]
     ApiNotEnabled { backtrace: Backtrace },
+    #[snafu(display("Rate limit of the Google Drive API exceeded"))]
+    RateLimited {
+        retry_after: Option<std::time::Duration>,
+    },
+    #[snafu(display("Quota of the Google Drive API exceeded"))]
+    QuotaExceeded { backtrace: Backtrace },
+    #[snafu(display("Permission denied by the Google Drive API: {}", reason))]
+    PermissionDenied { reason: String },
     #[snafu(display("Service Account does not have viewer permission on Shared Drive"))]
     DriveNotFound { backtrace: Backtrace },
     #[snafu(display("Unable to connect to the Google Drive API"))]
     Connection { source: reqwest::Error },
     #[snafu(display("Unable to parse/deserialise the JSON response"))]
     Deserialisation { source: reqwest::Error },
-    #[snafu(display("Invalid Service Account Credentials"))]
+    #[snafu(display("Invalid credentials, the access token was rejected"))]
     InvalidCredentials { backtrace: Backtrace },
+    #[snafu(display("Unable to obtain an access token: {}", source))]
+    Token { source: TokenError },
     #[snafu(display("An unknown error occured!"))]
     UnknownStatus { status: StatusCode },
     #[snafu(display("The Google Drive API is having some issues"))]
@@ -36,109 +57,285 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Keep the errors of the built-in token providers, so connection errors are still retried.
+    fn from_token_error(error: TokenError) -> Self {
+        match error.downcast::<Error>() {
+            Ok(error) => *error,
+            Err(source) => Error::Token { source },
+        }
+    }
+}
+
 fn to_backoff_error(error: Error) -> backoff::Error<Error> {
     match error {
-        Error::Connection { .. } | Error::Server { .. } => backoff::Error::Transient(error),
+        Error::Connection { .. } | Error::Server { .. } | Error::RateLimited { .. } => {
+            backoff::Error::Transient(error)
+        }
         _ => backoff::Error::Permanent(error),
     }
 }
 
+/// Send the request and deserialise the JSON response, or map the error response to an [`Error`].
+pub(crate) async fn request_json<T>(client: &Client, request: reqwest::Request) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    trace!(url_path = %request.url().path(), "making request");
+
+    let response = client.execute(request).await.context(Connection)?;
+
+    let status = response.status();
+    if status.is_success() {
+        let response: T = response.json().await.context(Deserialisation)?;
+        return Ok(response);
+    }
+
+    if status.is_server_error() {
+        return Err(Server { status }.build());
+    }
+
+    let retry_after = retry_after(&response);
+
+    let error = match status {
+        StatusCode::NOT_FOUND => DriveNotFound.build(),
+        StatusCode::TOO_MANY_REQUESTS => RateLimited { retry_after }.build(),
+        StatusCode::FORBIDDEN => match error_reason(response).await.as_deref() {
+            Some("rateLimitExceeded") | Some("userRateLimitExceeded") => {
+                RateLimited { retry_after }.build()
+            }
+            Some("dailyLimitExceeded") | Some("quotaExceeded") => QuotaExceeded.build(),
+            Some("accessNotConfigured") => ApiNotEnabled.build(),
+            reason => PermissionDenied {
+                reason: reason.unwrap_or("unknown"),
+            }
+            .build(),
+        },
+        StatusCode::UNAUTHORIZED => InvalidCredentials.build(),
+        _ => Error::UnknownStatus { status },
+    };
+
+    Err(error)
+}
+
+/// The delay of the `Retry-After` header, if any, either in seconds or until an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<std::time::Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?;
+
+    if let Ok(seconds) = value.parse() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+
+    // A date in the past does not ask for any delay.
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or_default())
+}
+
+/// The reason of the first error within the body of a Google API error response.
+async fn error_reason(response: reqwest::Response) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Response {
+        error: ErrorBody,
+    }
+
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        #[serde(default)]
+        errors: Vec<ErrorDetail>,
+    }
+
+    #[derive(Deserialize)]
+    struct ErrorDetail {
+        reason: String,
+    }
+
+    let response: Response = response.json().await.ok()?;
+    response
+        .error
+        .errors
+        .into_iter()
+        .next()
+        .map(|detail| detail.reason)
+}
+
+/// Base URL of the Google Drive API.
+const API_BASE_URL: &str = "https://www.googleapis.com/drive/v3";
+
+/// URL of the Google OAuth 2.0 token endpoint.
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
 pub struct Fetcher {
-    account: Account,
     client: Client,
-    refresh_token: RefreshToken,
+    accounts: AccountPool,
+    api_base_url: String,
+    token_url: String,
+    retry_policy: RetryPolicy,
+    on_retry: Option<RetryHook>,
+    token_cache: Option<Pool>,
 }
 
 impl Fetcher {
-    pub fn new(client: Client, account: Account) -> Fetcher {
+    pub fn new(
+        client: Client,
+        providers: Vec<Box<dyn TokenProvider>>,
+        api_base_url: String,
+        token_url: String,
+    ) -> Fetcher {
         let scope = Scope::builder()
             .scope("https://www.googleapis.com/auth/drive.readonly")
             .lifetime(Duration::hours(1))
             .build();
 
-        let refresh_token = RefreshToken::new(scope);
+        let accounts = AccountPool::new(providers, scope);
 
         Self {
-            account,
             client,
-            refresh_token,
+            accounts,
+            api_base_url,
+            token_url,
+            retry_policy: RetryPolicy::default(),
+            on_retry: None,
+            token_cache: None,
         }
     }
 
-    pub fn builder(account: Account) -> FetchBuilder {
-        FetchBuilder::new(account)
+    /// The URL of an endpoint of the Google Drive API, such as `/files`.
+    fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.api_base_url, path)
     }
 
-    async fn with_auth<T>(self: Arc<Fetcher>, request: reqwest::RequestBuilder) -> Result<T>
-    where
-        T: serde::de::DeserializeOwned,
-    {
-        let AccessToken { token, .. } = self.refresh_token.access_token(self.clone()).await?;
-
-        let request = request.bearer_auth(token).build().unwrap();
-
-        self.make_request_inner(request).await
+    pub fn builder(provider: Box<dyn TokenProvider>) -> FetchBuilder {
+        FetchBuilder::new(provider)
     }
 
-    async fn make_request_inner<T>(self: Arc<Fetcher>, request: reqwest::Request) -> Result<T>
+    /// Send the request authenticated by the account with the provided index within the pool.
+    async fn with_auth<T>(&self, account: usize, request: reqwest::RequestBuilder) -> Result<T>
     where
         T: serde::de::DeserializeOwned,
     {
-        trace!(url_path = %request.url().path(), "making request");
-
-        let response = self.client.execute(request).await.context(Connection)?;
-
-        let status = response.status();
-        if status.is_success() {
-            let response: T = response.json().await.context(Deserialisation)?;
-            return Ok(response);
-        }
-
-        if status.is_server_error() {
-            return Err(Server { status }.build());
-        }
-
-        let error = match status {
-            StatusCode::NOT_FOUND => DriveNotFound.build(),
-            StatusCode::FORBIDDEN => {
-                // TODO: Decode request to figure out whether it is a rate limit error
-                ApiNotEnabled.build()
+        let refresh_token = self.accounts.refresh_token(account);
+        let AccessToken { token, .. } = refresh_token.access_token(self).await?;
+
+        // Kept to send the request once more when the token is rejected.
+        let retry = request.try_clone();
+        let request = request.bearer_auth(&token).build().unwrap();
+
+        let result = request_json(&self.client, request).await;
+        if let Err(Error::InvalidCredentials { .. }) = &result {
+            // A cached token may have been revoked before it expired.
+            refresh_token.invalidate(&token, self).await;
+
+            if let Some(retry) = retry {
+                let AccessToken { token, .. } = refresh_token.access_token(self).await?;
+                let request = retry.bearer_auth(token).build().unwrap();
+                return request_json(&self.client, request).await;
             }
-            StatusCode::UNAUTHORIZED => InvalidCredentials.build(),
-            _ => Error::UnknownStatus { status },
-        };
+        }
 
-        Err(error)
+        result
     }
 
     async fn with_retry<T>(self: Arc<Fetcher>, request: reqwest::RequestBuilder) -> Result<T>
     where
         T: serde::de::DeserializeOwned + Send + 'static,
     {
+        // Captured before spawning, as the task-local stats do not carry over to the new task.
+        let stats = stats::current();
+
         let future = async move {
-            let response: T =
-                backoff::future::retry(backoff::ExponentialBackoff::default(), || {
-                    let request = request.try_clone().expect("Could not clone request");
-
-                    async {
-                        let response: T = self
-                            .clone()
-                            .with_auth(request)
-                            .await
-                            .map_err(to_backoff_error)
-                            .tap_err(|error| match error {
-                                backoff::Error::Permanent(error) => {
-                                    error!(%error, "non-retryable error occured")
-                                }
-                                backoff::Error::Transient(error) => {
-                                    warn!(%error, "retryable error occured")
-                                }
-                            })?;
-
-                        Ok(response)
+            let attempts = AtomicUsize::new(0);
+            let next_delay = NextDelay::default();
+
+            let operation = || {
+                let fetch = self.clone();
+                let request = request.try_clone().expect("Could not clone request");
+                let next_delay = next_delay.clone();
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+
+                if let Some(stats) = &stats {
+                    stats.add_request();
+                }
+
+                async move {
+                    let account = fetch.accounts.select();
+
+                    let error = match fetch.with_auth(account, request).await {
+                        Ok(response) => return Ok(response),
+                        Err(error) => error,
+                    };
+
+                    // Fail over to another account when this account hits a rate limit or quota.
+                    let fail_over = match &error {
+                        Error::RateLimited { retry_after } => {
+                            fetch.accounts.rate_limited(account, *retry_after);
+                            fetch.accounts.any_available()
+                        }
+                        Error::QuotaExceeded { .. } => {
+                            fetch.accounts.quota_exceeded(account);
+                            fetch.accounts.any_available()
+                        }
+                        _ => false,
+                    };
+
+                    let error = match fail_over {
+                        true => backoff::Error::Transient(error),
+                        false => to_backoff_error(error),
+                    };
+
+                    // Give up once the maximum number of attempts has been reached.
+                    let error = match error {
+                        backoff::Error::Transient(error) if !fetch.retry_policy.allows(attempt) => {
+                            backoff::Error::Permanent(error)
+                        }
+                        error => error,
+                    };
+
+                    match &error {
+                        backoff::Error::Permanent(error) => {
+                            error!(%error, attempt, "non-retryable error occured")
+                        }
+                        backoff::Error::Transient(error) => {
+                            warn!(%error, attempt, "retryable error occured")
+                        }
+                    }
+
+                    // Retry right away when another account can take over,
+                    // otherwise wait for at least as long as Google asks before the next attempt.
+                    match &error {
+                        backoff::Error::Transient(_) if fail_over => next_delay.immediately(),
+                        backoff::Error::Transient(Error::RateLimited {
+                            retry_after: Some(delay),
+                        }) => next_delay.at_least(*delay),
+                        _ => {}
                     }
-                })
-                .await?;
+
+                    Err(error)
+                }
+            };
+
+            let notify = |error: Error, delay| {
+                if let Some(stats) = &stats {
+                    stats.add_retry();
+                }
+
+                if let Some(on_retry) = &self.on_retry {
+                    let error = crate::Error::from(error);
+
+                    on_retry(&Retry {
+                        attempt: attempts.load(Ordering::SeqCst),
+                        delay,
+                        error: &error,
+                    });
+                }
+            };
+
+            let policy = self.retry_policy.backoff(next_delay.clone());
+            let response: T = backoff::future::retry_notify(policy, operation, notify).await?;
 
             Ok(response)
         };
@@ -148,22 +345,67 @@ impl Fetcher {
 }
 
 pub struct FetchBuilder {
-    account: Account,
+    providers: Vec<Box<dyn TokenProvider>>,
     client: ClientBuilder,
+    api_base_url: String,
+    token_url: String,
+    retry_policy: RetryPolicy,
+    on_retry: Option<RetryHook>,
+    token_cache: Option<Pool>,
 }
 
 impl FetchBuilder {
-    pub fn new(account: Account) -> Self {
+    pub fn new(provider: Box<dyn TokenProvider>) -> Self {
         Self {
             client: ClientBuilder::new(),
-            account,
+            providers: vec![provider],
+            api_base_url: API_BASE_URL.to_owned(),
+            token_url: TOKEN_URL.to_owned(),
+            retry_policy: RetryPolicy::default(),
+            on_retry: None,
+            token_cache: None,
         }
     }
 
     pub fn build(self) -> Fetcher {
         let client = self.client.build().unwrap();
 
-        Fetcher::new(client, self.account)
+        let mut fetcher = Fetcher::new(client, self.providers, self.api_base_url, self.token_url);
+        fetcher.retry_policy = self.retry_policy;
+        fetcher.on_retry = self.on_retry;
+        fetcher.token_cache = self.token_cache;
+        fetcher
+    }
+
+    pub fn add_provider(mut self, provider: Box<dyn TokenProvider>) -> Self {
+        self.providers.push(provider);
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    pub fn on_retry(mut self, hook: RetryHook) -> Self {
+        self.on_retry = Some(hook);
+        self
+    }
+
+    /// Store the access tokens in the database, so they are reused after a restart.
+    pub(crate) fn token_cache(mut self, pool: Pool) -> Self {
+        self.token_cache = Some(pool);
+        self
+    }
+
+    pub fn api_base_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.api_base_url = url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    pub fn token_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.token_url = url.into();
+        self
     }
 
     pub fn proxy<U: IntoUrl>(mut self, url: U) -> Self {
@@ -194,8 +436,36 @@ impl Item {
             Item::Folder(folder) => folder.id,
         }
     }
+
+    /// Items outside of Shared Drives have no drive ID, so assign the provided drive,
+    /// and place the items without any parent in the virtual folder of shared items.
+    fn assign_to_drive(&mut self, drive_id: &str) {
+        let (item_drive_id, parents) = match self {
+            Item::File(file) => (&mut file.drive_id, &mut file.parents),
+            Item::Folder(folder) => (&mut folder.drive_id, &mut folder.parents),
+        };
+
+        if !item_drive_id.is_empty() {
+            return;
+        }
+
+        *item_drive_id = drive_id.to_owned();
+
+        if parents.is_empty() {
+            parents.push(shared_with_me_id(drive_id));
+        }
+    }
 }
 
+/// MIME type Google Drive uses for folders.
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// MIME type Google Drive uses for shortcuts.
+const SHORTCUT_MIME_TYPE: &str = "application/vnd.google-apps.shortcut";
+
+/// MIME type prefix of all Google Workspace types, including folders and shortcuts.
+const GOOGLE_APPS_MIME_TYPE_PREFIX: &str = "application/vnd.google-apps.";
+
 // Custom deserializer for Item to parse into the correct enum variant.
 impl<'de> Deserialize<'de> for Item {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
@@ -208,43 +478,93 @@ impl<'de> Deserialize<'de> for Item {
         #[serde(rename_all = "camelCase")]
         struct Mapping {
             id: String,
-            drive_id: String,
+            drive_id: Option<String>,
             md5_checksum: Option<String>,
+            mime_type: String,
             name: String,
-            #[serde(deserialize_with = "from_vec", rename = "parents")]
-            parent: Option<String>,
+            #[serde(default)]
+            parents: Vec<String>,
+            shortcut_details: Option<ShortcutDetails>,
             size: Option<String>,
             trashed: bool,
         }
 
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ShortcutDetails {
+            target_id: String,
+            target_mime_type: Option<String>,
+        }
+
         let Mapping {
             id,
             drive_id,
             md5_checksum,
+            mime_type,
             name,
-            parent,
+            mut parents,
+            shortcut_details,
             size,
             trashed,
         } = Mapping::deserialize(deserializer)?;
 
-        match (md5_checksum, size, parent) {
-            (Some(md5), Some(size), Some(parent)) => Ok(Self::File(File {
-                id,
-                drive_id,
-                md5,
-                name,
-                parent,
-                size: size.parse().map_err(D::Error::custom)?,
-                trashed,
-            })),
-            (_, _, parent) => Ok(Self::Folder(Folder {
+        // Every item in a Shared Drive has a parent, so fall back to the root folder.
+        // Items outside of Shared Drives are assigned to a drive by `Item::assign_to_drive`.
+        let drive_id = match drive_id {
+            Some(drive_id) => {
+                if parents.is_empty() {
+                    parents.push(drive_id.clone());
+                }
+
+                drive_id
+            }
+            None => String::new(),
+        };
+
+        if mime_type == FOLDER_MIME_TYPE {
+            return Ok(Self::Folder(Folder {
                 id,
                 drive_id,
                 name,
                 trashed,
-                parent,
-            })),
+                parents,
+            }));
         }
+
+        let kind = match mime_type.as_str() {
+            SHORTCUT_MIME_TYPE => FileKind::Shortcut,
+            m if m.starts_with(GOOGLE_APPS_MIME_TYPE_PREFIX) => FileKind::Document,
+            _ => FileKind::Binary,
+        };
+
+        // Google Workspace documents and shortcuts do not have any binary content,
+        // so their checksum and size are not stored even if Google reports them.
+        let (md5, size) = match kind {
+            FileKind::Binary => {
+                let size = size.map(|size| size.parse()).transpose();
+                (md5_checksum, size.map_err(D::Error::custom)?)
+            }
+            FileKind::Document | FileKind::Shortcut => (None, None),
+        };
+
+        let (target_id, target_mime_type) = match shortcut_details {
+            Some(details) => (Some(details.target_id), details.target_mime_type),
+            None => (None, None),
+        };
+
+        Ok(Self::File(File {
+            id,
+            drive_id,
+            name,
+            trashed,
+            parents,
+            kind,
+            mime_type,
+            md5,
+            size,
+            target_id,
+            target_mime_type,
+        }))
     }
 }
 
@@ -299,12 +619,3 @@ impl<'de> Deserialize<'de> for Change {
         }
     }
 }
-
-/// Convert a `Vec<String>` into an `Option<String>` with the first element of the vec.
-fn from_vec<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let parents: Vec<String> = Deserialize::deserialize(deserializer)?;
-    Ok(parents.into_iter().next())
-}
@@ -1,13 +1,17 @@
-use super::{Fetcher, Result};
+use super::{Corpus, Fetcher, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 impl Fetcher {
-    pub async fn start_page_token(self: Arc<Fetcher>, drive_id: &str) -> Result<String> {
+    pub async fn start_page_token(
+        self: Arc<Fetcher>,
+        drive_id: &str,
+        corpus: Corpus,
+    ) -> Result<String> {
         #[derive(Serialize)]
         #[serde(rename_all = "camelCase")]
         struct Query<'a> {
-            drive_id: &'a str,
+            drive_id: Option<&'a str>,
             fields: &'a str,
             supports_all_drives: bool,
         }
@@ -18,15 +22,19 @@ impl Fetcher {
             start_page_token: String,
         }
 
+        // The start page token of My Drive is the one of the user.
         let query = Query {
-            drive_id,
+            drive_id: match corpus {
+                Corpus::Drive => Some(drive_id),
+                Corpus::User => None,
+            },
             fields: "startPageToken",
             supports_all_drives: true,
         };
 
         let request = self
             .client
-            .get("https://www.googleapis.com/drive/v3/changes/startPageToken")
+            .get(self.api_url("/changes/startPageToken"))
             .query(&query);
 
         let Response { start_page_token } = self.with_retry(request).await?;
@@ -0,0 +1,97 @@
+use super::auth::{RefreshToken, Scope};
+use crate::credentials::TokenProvider;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long an account is avoided after hitting a rate limit without a `Retry-After` header.
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// How long an account is avoided after exceeding its quota.
+const QUOTA_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+struct Credential {
+    refresh_token: RefreshToken,
+    /// The account is avoided until this moment.
+    cooldown: Mutex<Option<Instant>>,
+}
+
+impl Credential {
+    fn cooling_down(&self, now: Instant) -> Option<Instant> {
+        let cooldown = *self.cooldown.lock().unwrap();
+        cooldown.filter(|until| *until > now)
+    }
+}
+
+/// The token providers of Bernard, which take turns so requests are spread across their quotas.
+pub(crate) struct AccountPool {
+    accounts: Vec<Credential>,
+    next: AtomicUsize,
+}
+
+impl AccountPool {
+    pub(crate) fn new(providers: Vec<Box<dyn TokenProvider>>, scope: Scope) -> Self {
+        let accounts = providers
+            .into_iter()
+            .map(|provider| Credential {
+                refresh_token: RefreshToken::new(provider, scope.clone()),
+                cooldown: Mutex::new(None),
+            })
+            .collect();
+
+        Self {
+            accounts,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The next account in turn which is not cooling down.
+    /// When all accounts are cooling down, the account which recovers first is selected.
+    pub(crate) fn select(&self) -> usize {
+        let now = Instant::now();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        let mut soonest: Option<(usize, Instant)> = None;
+
+        for offset in 0..self.accounts.len() {
+            let index = (start + offset) % self.accounts.len();
+
+            match self.accounts[index].cooling_down(now) {
+                None => return index,
+                Some(until) if soonest.map(|(_, soonest)| until < soonest).unwrap_or(true) => {
+                    soonest = Some((index, until))
+                }
+                Some(_) => {}
+            }
+        }
+
+        soonest.map(|(index, _)| index).unwrap_or(0)
+    }
+
+    pub(crate) fn refresh_token(&self, index: usize) -> &RefreshToken {
+        &self.accounts[index].refresh_token
+    }
+
+    /// Avoid the account after it hit a rate limit, for as long as Google asks if known.
+    pub(crate) fn rate_limited(&self, index: usize, retry_after: Option<Duration>) {
+        self.cool_down(index, retry_after.unwrap_or(RATE_LIMIT_COOLDOWN));
+    }
+
+    /// Avoid the account after it exceeded its quota.
+    pub(crate) fn quota_exceeded(&self, index: usize) {
+        self.cool_down(index, QUOTA_COOLDOWN);
+    }
+
+    fn cool_down(&self, index: usize, duration: Duration) {
+        *self.accounts[index].cooldown.lock().unwrap() = Some(Instant::now() + duration);
+    }
+
+    /// Whether any account is not cooling down, so a request can be sent right away.
+    pub(crate) fn any_available(&self) -> bool {
+        let now = Instant::now();
+
+        self.accounts
+            .iter()
+            .any(|account| account.cooling_down(now).is_none())
+    }
+}
@@ -0,0 +1,130 @@
+use backoff::backoff::Backoff;
+use backoff::ExponentialBackoff;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How failed requests to the Google APIs are retried.
+///
+/// Only transient errors are retried, such as connection errors, server errors and rate limits.
+/// The defaults retry for up to 15 minutes.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The delay before the first retry.
+    pub initial_interval: Duration,
+    /// The factor the delay is multiplied with after every retry.
+    pub multiplier: f64,
+    /// The largest delay between two attempts.
+    pub max_interval: Duration,
+    /// Stop retrying once this much time has passed since the first attempt, or never when `None`.
+    pub max_elapsed_time: Option<Duration>,
+    /// The maximum number of attempts including the first attempt, or unlimited when `None`.
+    pub max_attempts: Option<usize>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_interval: Duration::from_millis(500),
+            multiplier: 1.5,
+            max_interval: Duration::from_secs(60),
+            max_elapsed_time: Some(Duration::from_secs(15 * 60)),
+            max_attempts: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry a failed request.
+    pub fn never() -> Self {
+        Self {
+            max_attempts: Some(1),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn backoff(&self, next_delay: NextDelay) -> RetryBackoff {
+        let backoff = ExponentialBackoff {
+            current_interval: self.initial_interval,
+            initial_interval: self.initial_interval,
+            multiplier: self.multiplier,
+            max_interval: self.max_interval,
+            max_elapsed_time: self.max_elapsed_time,
+            ..ExponentialBackoff::default()
+        };
+
+        RetryBackoff {
+            backoff,
+            next_delay,
+        }
+    }
+
+    /// Whether another attempt is allowed after the provided number of attempts.
+    pub(crate) fn allows(&self, attempts: usize) -> bool {
+        self.max_attempts
+            .map(|max_attempts| attempts < max_attempts)
+            .unwrap_or(true)
+    }
+}
+
+/// A failed request which is about to be retried.
+#[derive(Debug)]
+pub struct Retry<'a> {
+    /// The number of failed attempts so far.
+    pub attempt: usize,
+    /// The delay before the next attempt, at least as long as the `Retry-After` header asks for.
+    pub delay: Duration,
+    pub error: &'a crate::Error,
+}
+
+/// Called on every retry, see [`BernardBuilder::on_retry`](crate::BernardBuilder::on_retry).
+pub(crate) type RetryHook = Arc<dyn Fn(&Retry<'_>) + Send + Sync>;
+
+/// The delay the last failed attempt asks for, shared between the attempts and the backoff.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct NextDelay(Arc<Mutex<Option<Delay>>>);
+
+#[derive(Debug)]
+enum Delay {
+    /// Google asked to wait with a `Retry-After` header.
+    AtLeast(Duration),
+    /// Another account takes over, so there is no need to wait.
+    Immediately,
+}
+
+impl NextDelay {
+    pub(crate) fn at_least(&self, delay: Duration) {
+        *self.0.lock().unwrap() = Some(Delay::AtLeast(delay));
+    }
+
+    pub(crate) fn immediately(&self) {
+        *self.0.lock().unwrap() = Some(Delay::Immediately);
+    }
+
+    fn take(&self) -> Option<Delay> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// The exponential backoff of a [`RetryPolicy`],
+/// which is overridden by the delay the last failed attempt asks for.
+pub(crate) struct RetryBackoff {
+    backoff: ExponentialBackoff,
+    next_delay: NextDelay,
+}
+
+impl Backoff for RetryBackoff {
+    fn reset(&mut self) {
+        self.backoff.reset();
+    }
+
+    fn next_backoff(&mut self) -> Option<Duration> {
+        // Still called when retrying immediately, so the maximum elapsed time is respected.
+        let interval = self.backoff.next_backoff()?;
+
+        match self.next_delay.take() {
+            Some(Delay::AtLeast(delay)) => Some(interval.max(delay)),
+            Some(Delay::Immediately) => Some(Duration::default()),
+            None => Some(interval),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The requests to the Google Drive API made on behalf of a single synchronisation.
+#[derive(Debug, Default)]
+pub(crate) struct RequestStats {
+    requests: AtomicUsize,
+    retries: AtomicUsize,
+}
+
+impl RequestStats {
+    /// The number of attempted requests, including retries.
+    pub(crate) fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn retries(&self) -> usize {
+        self.retries.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn add_request(&self) {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(super) fn add_retry(&self) {
+        self.retries.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+tokio::task_local! {
+    // The fetcher is shared between concurrent synchronisations, so the stats follow the task instead.
+    static REQUEST_STATS: Arc<RequestStats>;
+}
+
+/// Count the requests made while running the future.
+pub(crate) async fn track_requests<F: Future>(stats: Arc<RequestStats>, future: F) -> F::Output {
+    REQUEST_STATS.scope(stats, future).await
+}
+
+/// The stats of the current synchronisation, if any.
+pub(super) fn current() -> Option<Arc<RequestStats>> {
+    REQUEST_STATS.try_with(|stats| stats.clone()).ok()
+}
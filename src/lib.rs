@@ -1,26 +1,42 @@
 use database::Pool;
-use fetch::{FetchBuilder, Fetcher};
+use fetch::{Change, FetchBuilder, Fetcher, RequestStats};
+use chrono::Utc;
+use model::{Corpus, Drive};
+use futures::prelude::*;
 use jsonwebtoken::EncodingKey;
 use reqwest::IntoUrl;
 use serde::Deserialize;
 use snafu::{ResultExt, Snafu};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::info;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
 
 mod changes;
+mod credentials;
 mod database;
 mod fetch;
 mod model;
+mod report;
 
 pub use changes::Changes;
-pub use model::{ChangedFile, ChangedFolder, ChangedPath, File, Folder, InnerPath, Path};
+pub use credentials::{
+    AccessToken, AuthorizedUser, StaticToken, TokenError, TokenProvider, TokenRequest,
+};
+pub use fetch::{Retry, RetryPolicy};
+pub use model::{
+    ChangeSet, ChangedFile, ChangedFolder, ChangedPath, Entry, File, FileKind, Folder, InnerPath,
+    ItemCounts, Path, RunKind, ShortcutPolicy, Stat, SyncRun,
+};
+pub use report::SyncReport;
 
 #[derive(Debug, Snafu)]
 pub struct Error(InnerError);
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "snake_case")]
 pub enum ErrorKind {
     Database,
     Network,
@@ -38,14 +54,19 @@ enum InnerError {
     Network { source: fetch::Error },
     #[snafu(display("Received a partial change list from Google. Database error: {}", source))]
     PartialChangeList { source: sqlx::Error },
-    #[snafu(display("Cannot read the Service Account JWK file: {:?}. IO error: {}", file_name, source))]
+    #[snafu(display("Cannot read the Service Account JWK from {}. IO error: {}", origin, source))]
     WhereIsJWK {
-        file_name: PathBuf,
+        origin: String,
         source: std::io::Error,
     },
-    #[snafu(display("Invalid Service Account JWK file: {:?}. JSON error: {}", file_name, source))]
+    #[snafu(display("Cannot read the Service Account JWK from environment variable {:?}: {}", variable, source))]
+    MissingJWKVariable {
+        variable: String,
+        source: std::env::VarError,
+    },
+    #[snafu(display("Invalid Service Account JWK from {}. JSON error: {}", origin, source))]
     InvalidJWK {
-        file_name: PathBuf,
+        origin: String,
         source: serde_json::Error,
     },
 }
@@ -58,7 +79,7 @@ impl Error {
             Database { .. } => ErrorKind::Database,
             Network { .. } => ErrorKind::Network,
             PartialChangeList { .. } => ErrorKind::PartialChangeList,
-            WhereIsJWK { .. } => ErrorKind::WhereIsJWK,
+            WhereIsJWK { .. } | MissingJWKVariable { .. } => ErrorKind::WhereIsJWK,
             InvalidJWK { .. } => ErrorKind::InvalidJWK,
         }
     }
@@ -94,80 +115,546 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct Bernard {
     fetch: Arc<Fetcher>,
     pool: Pool,
+    shortcut_policy: ShortcutPolicy,
+    // Serialises the writes of concurrently synchronised drives into SQLite.
+    write_lock: Mutex<()>,
 }
 
 // TODO: Better names
 pub enum SyncKind<'a> {
-    Full,
+    Full(SyncReport),
     Partial(Changes<'a>),
+    /// The Shared Drive has been deleted or is no longer accessible,
+    /// so all of its folders and files have been removed from the database.
+    Removed(Changes<'a>),
+}
+
+impl SyncKind<'_> {
+    /// What the synchronisation has done.
+    pub fn report(&self) -> &SyncReport {
+        match self {
+            Self::Full(report) => report,
+            Self::Partial(changes) | Self::Removed(changes) => changes.report(),
+        }
+    }
 }
 
 impl Bernard {
-    pub fn builder<S: Into<String>>(database_path: S, account: Account) -> BernardBuilder {
-        BernardBuilder::new(database_path, account)
+    /// Start building a Bernard, authenticated by any [`TokenProvider`] such as an [`Account`].
+    pub fn builder<S, P>(database_path: S, provider: P) -> BernardBuilder
+    where
+        S: Into<String>,
+        P: TokenProvider + 'static,
+    {
+        BernardBuilder::new(database_path, provider)
     }
 
     pub async fn close(self) {
         self.pool.close().await
     }
 
-    #[tracing::instrument(level = "info", skip(self))]
-    pub async fn sync_drive<'a>(&'a self, drive_id: &'a str) -> Result<SyncKind<'a>> {
-        // Always clear changelog for consistent database state when sync_drive is called.
-        database::clear_changelog(drive_id, &self.pool).await?;
+    /// All paths within the provided Shared Drive.
+    /// Shortcuts are exposed according to the [`ShortcutPolicy`] of the builder.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn paths(&self, drive_id: &str) -> Result<Vec<Path>> {
+        database::get_paths(drive_id, self.shortcut_policy, &self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
 
-        let drive = database::get_drive(drive_id, &self.pool).await?;
+    /// Stream all paths within the provided Shared Drive without loading them into memory at once.
+    /// Shortcuts are exposed according to the [`ShortcutPolicy`] of the builder.
+    pub fn walk<'a>(&'a self, drive_id: &'a str) -> impl Stream<Item = Result<Path>> + 'a {
+        database::stream_paths(drive_id, self.shortcut_policy, &self.pool).map_err(|e| e.into())
+    }
 
-        match drive {
-            None => {
-                info!("starting full synchronisation");
-                let page_token = self.fetch.clone().start_page_token(drive_id).await?;
+    /// All paths of a folder or file within the drive, one for every parent.
+    /// Shortcuts are not followed.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn path_of(&self, drive_id: &str, id: &str) -> Result<Vec<Path>> {
+        database::get_paths_of(id, drive_id, &self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
 
-                // Might want to sleep between page_token and items
-                let name = self.fetch.clone().drive_name(drive_id).await?;
-                let items = self.fetch.clone().all_files(drive_id).await?;
+    /// The folders and files at the provided path within the Shared Drive, such as `/a/b/c`.
+    /// Google Drive allows multiple items with the same name, so a path can point to multiple entries.
+    /// Shortcuts are not followed.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn lookup(&self, drive_id: &str, path: &str) -> Result<Vec<Entry>> {
+        database::lookup(drive_id, path, &self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /// The folders and files directly within the provided folder of the drive.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn children(&self, drive_id: &str, folder_id: &str) -> Result<Vec<Entry>> {
+        database::get_children(folder_id, drive_id, &self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /// The metadata of a folder or file within the drive, or `None` when it does not exist.
+    /// Items are only unique within a drive, as an item shared with the user from a Shared Drive
+    /// is also part of My Drive.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn stat(&self, drive_id: &str, id: &str) -> Result<Option<Stat>> {
+        let entry = database::get_entry(id, drive_id, &self.pool).await?;
+        Ok(entry.map(|entry| entry.into()))
+    }
+
+    /// The changes of the drive after the provided generation, oldest first, which are retained
+    /// until every consumer has acknowledged them, see [`Bernard::acknowledge`].
+    /// Without consumers, only the changes of the last synchronisation are retained.
+    ///
+    /// Start with a cursor of `0`, or the cursor of a consumer, see [`Bernard::cursor`].
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn changes_since(&self, drive_id: &str, cursor: i64) -> Result<Vec<ChangeSet>> {
+        database::get_changes_since(drive_id, cursor, &self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
 
-                database::add_drive(drive_id, &name, &page_token, items, &self.pool).await?;
+    /// The last generation the consumer has acknowledged for the drive, or `0` when none.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn cursor(&self, consumer: &str, drive_id: &str) -> Result<i64> {
+        let cursor = database::get_cursor(consumer, drive_id, &self.pool).await?;
+        Ok(cursor.unwrap_or(0))
+    }
+
+    /// Retain every following change of the drive until the consumer has acknowledged it.
+    /// Register before the first synchronisation the consumer must not miss,
+    /// as every synchronisation prunes the earlier changes of a drive without consumers.
+    /// Registering a known consumer keeps its cursor.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn register_consumer(&self, consumer: &str, drive_id: &str) -> Result<()> {
+        let _write = self.write_lock.lock().await;
+        database::register_consumer(consumer, drive_id, &self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Mark every change of the drive up to and including the generation as processed by the consumer.
+    /// An unknown consumer is registered, see [`Bernard::register_consumer`].
+    /// Each consumer reads at its own pace, the changes are only pruned once all of them have acknowledged.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn acknowledge(&self, consumer: &str, drive_id: &str, generation: i64) -> Result<()> {
+        let _write = self.write_lock.lock().await;
+        database::acknowledge(consumer, drive_id, generation, &self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Forget the consumer on every drive, so it no longer holds back [`Bernard::prune_changes`].
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn remove_consumer(&self, consumer: &str) -> Result<()> {
+        let _write = self.write_lock.lock().await;
+        database::remove_consumer(consumer, &self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Remove the retained changes of the drive which every consumer has acknowledged,
+    /// or all of them when the drive has no consumers. Returns the number of removed generations.
+    ///
+    /// Every synchronisation already prunes the earlier changes up to the slowest consumer,
+    /// this also removes the changes of the last one.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn prune_changes(&self, drive_id: &str) -> Result<u64> {
+        let _write = self.write_lock.lock().await;
+        let pruned = database::prune_history(drive_id, &self.pool).await?;
+
+        Ok(pruned)
+    }
+
+    /// Synchronise the Shared Drive, or My Drive when it has been synchronised before
+    /// by [`Bernard::sync_my_drive`].
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn sync_drive<'a>(&'a self, drive_id: &str) -> Result<SyncKind<'a>> {
+        let stats = Arc::new(RequestStats::default());
+        self.sync(drive_id, Corpus::Drive, stats).await
+    }
+
+    /// The ID of the root folder of the My Drive of the user, which Bernard uses as its drive ID.
+    pub async fn my_drive_id(&self) -> Result<String> {
+        let root = self.fetch.clone().my_drive().await?;
+        Ok(root.id)
+    }
 
-                Ok(SyncKind::Full)
+    /// Synchronise the My Drive of the user, see [`Bernard::my_drive_id`] for its drive ID.
+    ///
+    /// The items shared with the user whose parents are not part of My Drive
+    /// are placed in the virtual `Shared with me` folder, such as `/Shared with me/file.txt`.
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn sync_my_drive(&self) -> Result<SyncKind<'_>> {
+        // The lookup of the drive ID counts towards the requests of the synchronisation.
+        let stats = Arc::new(RequestStats::default());
+        let drive_id = fetch::track_requests(stats.clone(), self.my_drive_id()).await?;
+        self.sync(&drive_id, Corpus::User, stats).await
+    }
+
+    /// Synchronise the drive, where the corpus of a known drive takes precedence.
+    /// Every synchronisation is recorded, see [`Bernard::sync_history`].
+    /// The requests are tracked in `stats`, which may already hold the requests made beforehand.
+    async fn sync<'a>(
+        &'a self,
+        drive_id: &str,
+        corpus: Corpus,
+        stats: Arc<RequestStats>,
+    ) -> Result<SyncKind<'a>> {
+        let mut report = SyncReport::default();
+
+        // Started before the database is touched, so that every failure is recorded.
+        // The run is full until the drive turns out to be known.
+        let mut run = SyncRun::start(drive_id, RunKind::Full, None);
+
+        let result = fetch::track_requests(
+            stats.clone(),
+            self.sync_full_or_partial(drive_id, corpus, &mut run, &mut report),
+        )
+        .await;
+
+        run.finished_at = Utc::now();
+        run.changes = report.listed as i64;
+        run.error = result.as_ref().err().map(|e| e.kind());
+
+        // The outcome of the synchronisation takes precedence over its record.
+        {
+            let _write = self.write_lock.lock().await;
+            if let Err(e) = database::record_sync_run(&mut run, report.generation, &self.pool).await
+            {
+                warn!("failed to record synchronisation: {}", e);
             }
+        }
+
+        report.folders = run.folders;
+        report.files = run.files;
+        report.requests = stats.requests();
+        report.retries = stats.retries();
+
+        Ok(match result? {
+            RunKind::Full => SyncKind::Full(report),
+            RunKind::Partial => SyncKind::Partial(Changes::new(self, drive_id, report)),
+            RunKind::Removed => SyncKind::Removed(Changes::new(self, drive_id, report)),
+        })
+    }
+
+    /// A full synchronisation of an unknown drive, and a partial one otherwise.
+    /// Returns what the synchronisation has done, the report is completed by `sync`.
+    async fn sync_full_or_partial(
+        &self,
+        drive_id: &str,
+        corpus: Corpus,
+        run: &mut SyncRun,
+        report: &mut SyncReport,
+    ) -> Result<RunKind> {
+        match database::get_drive(drive_id, &self.pool).await? {
+            None => self.sync_full(drive_id, corpus, run, report).await,
             Some(drive) => {
-                info!("starting partial synchronisation");
-
-                let (changes, new_page_token) = self
-                    .fetch
-                    .clone()
-                    .changes(drive_id, &drive.page_token)
-                    .await?;
-
-                match new_page_token == drive.page_token {
-                    // Do not perform database operation if no changes are available.
-                    true => {
-                        info!(page_token = %new_page_token, "page token has not changed");
+                run.kind = RunKind::Partial;
+                run.old_page_token = Some(drive.page_token.clone());
+                self.sync_partial(drive, run, report).await
+            }
+        }
+    }
+
+    async fn sync_full(
+        &self,
+        drive_id: &str,
+        corpus: Corpus,
+        run: &mut SyncRun,
+        report: &mut SyncReport,
+    ) -> Result<RunKind> {
+        let staged = 'listing: loop {
+            let staged = match database::get_staged_drive(drive_id, &self.pool).await? {
+                Some(staged) => {
+                    let next_page_token = &staged.next_page_token;
+                    info!(?next_page_token, "resuming full synchronisation");
+                    staged
+                }
+                None => {
+                    info!("starting full synchronisation");
+                    let start = Instant::now();
+                    let page_token = self
+                        .fetch
+                        .clone()
+                        .start_page_token(drive_id, corpus)
+                        .await?;
+
+                    // Might want to sleep between page_token and items
+                    let name = match corpus {
+                        Corpus::Drive => self.fetch.clone().drive_name(drive_id).await?,
+                        Corpus::User => self.fetch.clone().my_drive().await?.name,
+                    };
+                    report.fetch_duration += start.elapsed();
+
+                    let _write = self.write_lock.lock().await;
+                    let start = Instant::now();
+                    database::stage_drive(drive_id, &name, &page_token, corpus, &self.pool)
+                        .await?;
+                    let staged = database::get_staged_drive(drive_id, &self.pool)
+                        .await?
+                        .expect("staged drive has just been created");
+                    report.write_duration += start.elapsed();
+
+                    staged
+                }
+            };
+
+            if staged.complete {
+                break staged;
+            }
+
+            let pages = self
+                .fetch
+                .clone()
+                .all_files(drive_id, staged.corpus, staged.next_page_token.clone());
+            futures::pin_mut!(pages);
+
+            // The page token of an earlier process, which may have expired in the meantime.
+            let mut resumed = staged.next_page_token.is_some();
+
+            // Every page is committed on its own, so the lock is only held while writing.
+            loop {
+                let start = Instant::now();
+                let page = match pages.try_next().await {
+                    Ok(Some(page)) => page,
+                    Ok(None) => break 'listing staged,
+                    Err(fetch::Error::UnknownStatus { status })
+                        if resumed && status.is_client_error() =>
+                    {
+                        warn!(%status, "resumed page token was rejected, restarting the listing");
+                        let _write = self.write_lock.lock().await;
+                        database::unstage_drive(drive_id, &self.pool).await?;
+                        continue 'listing;
                     }
-                    false => {
-                        info!(page_token = %new_page_token, "page token has changed");
-                        database::merge_changes(drive_id, changes, &new_page_token, &self.pool)
-                            .await?;
+                    Err(e) => {
+                        return Err(self.abandon_full_sync(drive_id, staged.corpus, e).await)
                     }
                 };
+                report.fetch_duration += start.elapsed();
+                resumed = false;
+
+                let _write = self.write_lock.lock().await;
+                let start = Instant::now();
+                database::stage_page(drive_id, page, &self.pool).await?;
+                report.write_duration += start.elapsed();
+            }
+        };
+
+        run.new_page_token = Some(staged.page_token.clone());
+
+        // Counted from the staging tables, as an earlier process may have listed the first pages.
+        report.listed = database::count_staged_items(drive_id, &self.pool).await? as usize;
+
+        let _write = self.write_lock.lock().await;
+        let start = Instant::now();
+        let (orphaned_folders, orphaned_files, generation) =
+            database::promote_drive(&staged, &self.pool).await?;
+        report.write_duration += start.elapsed();
+
+        report.generation = generation;
+        report.orphaned_folders = orphaned_folders;
+        report.orphaned_files = orphaned_files;
+
+        Ok(RunKind::Full)
+    }
+
+    /// Remove the staged pages when the Shared Drive is gone, as the listing can never be resumed.
+    async fn abandon_full_sync(
+        &self,
+        drive_id: &str,
+        corpus: Corpus,
+        error: fetch::Error,
+    ) -> Error {
+        if corpus == Corpus::Drive && matches!(error, fetch::Error::DriveNotFound { .. }) {
+            if let Err(fetch::Error::DriveNotFound { .. }) =
+                self.fetch.clone().drive_name(drive_id).await
+            {
+                info!("drive is no longer available, removing staged pages");
+                let _write = self.write_lock.lock().await;
+                if let Err(e) = database::unstage_drive(drive_id, &self.pool).await {
+                    return e.into();
+                }
+            }
+        }
+
+        error.into()
+    }
+
+    async fn sync_partial(
+        &self,
+        drive: Drive,
+        run: &mut SyncRun,
+        report: &mut SyncReport,
+    ) -> Result<RunKind> {
+        info!("starting partial synchronisation");
+        let drive_id = drive.id.as_str();
+
+        let start = Instant::now();
+        let response = self
+            .fetch
+            .clone()
+            .changes(drive_id, drive.corpus, &drive.page_token)
+            .await;
+        report.fetch_duration += start.elapsed();
+
+        let (changes, new_page_token) = match response {
+            Ok(response) => response,
+            // The Shared Drive has been deleted or the Service Account lost access,
+            // unless the change list is not found for another reason, like an invalid page token.
+            Err(e @ fetch::Error::DriveNotFound { .. }) if drive.corpus == Corpus::Drive => {
+                match self.fetch.clone().drive_name(drive_id).await {
+                    Err(fetch::Error::DriveNotFound { .. }) => {
+                        run.kind = RunKind::Removed;
+                        return self.remove_drive(drive_id, report).await;
+                    }
+                    Ok(_) => return Err(e.into()),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        report.listed = changes.len();
+
+        let removed = changes.iter().any(|change| {
+            matches!(change, Change::DriveRemoved(id) if id == drive_id)
+        });
+
+        if removed {
+            run.kind = RunKind::Removed;
+            return self.remove_drive(drive_id, report).await;
+        }
 
-                Ok(SyncKind::Partial(Changes::new(self, drive_id)))
+        run.new_page_token = Some(new_page_token.clone());
+
+        match new_page_token == drive.page_token {
+            // Do not perform database operation if no changes are available.
+            true => {
+                info!(page_token = %new_page_token, "page token has not changed");
+            }
+            false => {
+                info!(page_token = %new_page_token, "page token has changed");
+                let _write = self.write_lock.lock().await;
+                let start = Instant::now();
+                report.generation = database::merge_changes(
+                    drive_id,
+                    drive.corpus,
+                    changes,
+                    &new_page_token,
+                    &self.pool,
+                )
+                .await?;
+                report.write_duration += start.elapsed();
+            }
+        };
+
+        Ok(RunKind::Partial)
+    }
+
+    /// Every synchronisation of the drive, newest first, including the failed ones.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn sync_history(&self, drive_id: &str) -> Result<Vec<SyncRun>> {
+        database::get_sync_runs(drive_id, &self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /// Synchronise every Shared Drive the Service Account has access to.
+    ///
+    /// New Shared Drives are fully synchronised and known Shared Drives partially.
+    /// Known Shared Drives which are no longer accessible are removed.
+    /// The result of every Shared Drive is returned separately, keyed by the drive ID.
+    #[tracing::instrument(level = "info", skip(self))]
+    pub async fn sync_all(&self) -> Result<HashMap<String, Result<SyncKind<'_>>>> {
+        let drives = self.fetch.clone().list_drives().await?;
+        info!(count = drives.len(), "discovered shared drives");
+
+        let mut drive_ids: Vec<String> = drives.into_iter().map(|drive| drive.id).collect();
+
+        // The full synchronisation of a drive which is not listed anymore can never be resumed.
+        for staged in database::get_staged_drives(&self.pool).await? {
+            if staged.corpus == Corpus::Drive && !drive_ids.contains(&staged.id) {
+                info!(drive_id = %staged.id, "drive is no longer available, removing staged pages");
+                let _write = self.write_lock.lock().await;
+                database::unstage_drive(&staged.id, &self.pool).await?;
             }
         }
+
+        // Known drives which are not listed anymore will be removed by `sync_drive`.
+        for drive in database::get_drives(&self.pool).await? {
+            if drive.corpus == Corpus::Drive && !drive_ids.contains(&drive.id) {
+                drive_ids.push(drive.id);
+            }
+        }
+
+        Ok(self.sync_drives(drive_ids, 1).await)
+    }
+
+    /// Synchronise multiple Shared Drives, with at most `concurrency` drives at the same time.
+    ///
+    /// The network requests of the drives overlap, while the database writes are serialised.
+    /// The result of every Shared Drive is returned separately, keyed by the drive ID,
+    /// so a failing drive does not abort the others.
+    #[tracing::instrument(level = "info", skip(self, drive_ids))]
+    pub async fn sync_drives<I, S>(
+        &self,
+        drive_ids: I,
+        concurrency: usize,
+    ) -> HashMap<String, Result<SyncKind<'_>>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        // A drive must not be synchronised twice at the same time.
+        let mut seen = HashSet::new();
+        let drive_ids: Vec<String> = drive_ids
+            .into_iter()
+            .map(Into::into)
+            .filter(|drive_id: &String| seen.insert(drive_id.clone()))
+            .collect();
+
+        stream::iter(drive_ids)
+            .map(|drive_id: String| async move {
+                let result = self.sync_drive(&drive_id).await;
+                (drive_id, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    async fn remove_drive(&self, drive_id: &str, report: &mut SyncReport) -> Result<RunKind> {
+        info!("drive is no longer available, removing");
+        let _write = self.write_lock.lock().await;
+        let start = Instant::now();
+        report.generation = database::remove_drive(drive_id, &self.pool).await?;
+        report.write_duration += start.elapsed();
+
+        Ok(RunKind::Removed)
     }
 }
 
 pub struct BernardBuilder {
     database_path: String,
     fetch: FetchBuilder,
+    shortcut_policy: ShortcutPolicy,
+    cache_tokens: bool,
 }
 
 impl BernardBuilder {
-    pub fn new<S: Into<String>>(database_path: S, account: Account) -> Self {
+    pub fn new<S, P>(database_path: S, provider: P) -> Self
+    where
+        S: Into<String>,
+        P: TokenProvider + 'static,
+    {
         Self {
             database_path: database_path.into(),
-            fetch: Fetcher::builder(account),
+            fetch: Fetcher::builder(Box::new(provider)),
+            shortcut_policy: ShortcutPolicy::default(),
+            cache_tokens: false,
         }
     }
 
@@ -175,9 +662,16 @@ impl BernardBuilder {
     pub async fn build(self) -> Result<Bernard> {
         let pool = database::establish_connection(&self.database_path).await?;
 
+        let fetch = match self.cache_tokens {
+            true => self.fetch.token_cache(pool.clone()),
+            false => self.fetch,
+        };
+
         Ok(Bernard {
-            fetch: Arc::new(self.fetch.build()),
+            fetch: Arc::new(fetch.build()),
             pool,
+            shortcut_policy: self.shortcut_policy,
+            write_lock: Mutex::new(()),
         })
     }
 
@@ -185,6 +679,57 @@ impl BernardBuilder {
         self.fetch = self.fetch.proxy(url);
         self
     }
+
+    /// Spread the requests across another token provider, such as a second Service Account.
+    ///
+    /// The providers take turns. A provider which hits a rate limit or exceeds its quota
+    /// is avoided for a while, and the failed request is retried with the next provider.
+    pub fn add_provider<P: TokenProvider + 'static>(mut self, provider: P) -> Self {
+        self.fetch = self.fetch.add_provider(Box::new(provider));
+        self
+    }
+
+    /// Use another base URL for the Google Drive API, such as a local emulator.
+    /// Defaults to `https://www.googleapis.com/drive/v3`.
+    pub fn api_base_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.fetch = self.fetch.api_base_url(url);
+        self
+    }
+
+    /// Use another OAuth 2.0 token endpoint, which is also the audience of the JWT.
+    /// Defaults to `https://oauth2.googleapis.com/token`.
+    pub fn token_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.fetch = self.fetch.token_url(url);
+        self
+    }
+
+    /// How failed requests to the Google APIs are retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.fetch = self.fetch.retry_policy(policy);
+        self
+    }
+
+    /// Call the hook before every retry of a failed request to the Google APIs.
+    pub fn on_retry<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Retry<'_>) + Send + Sync + 'static,
+    {
+        self.fetch = self.fetch.on_retry(Arc::new(hook));
+        self
+    }
+
+    /// Store the access tokens in the database, so a restarted process reuses them until they expire.
+    /// Only tokens of providers with a [`TokenProvider::cache_key`], such as an [`Account`], are stored.
+    pub fn cache_tokens(mut self, enabled: bool) -> Self {
+        self.cache_tokens = enabled;
+        self
+    }
+
+    /// Expose shortcuts as links (default) or as resolved copies of their target.
+    pub fn shortcut_policy(mut self, policy: ShortcutPolicy) -> Self {
+        self.shortcut_policy = policy;
+        self
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -204,21 +749,68 @@ impl TryFrom<String> for PrivateKey {
 pub struct Account {
     client_email: String,
     private_key: PrivateKey,
+    #[serde(skip)]
+    subject: Option<String>,
 }
 
 impl Account {
     pub fn from_file<P: AsRef<std::path::Path>>(file_name: P) -> Result<Self> {
-        let file = std::fs::File::open(file_name.as_ref()).context(WhereIsJWK {
-            file_name: file_name.as_ref(),
-        })?;
+        let origin = format!("file {:?}", file_name.as_ref());
+        let file =
+            std::fs::File::open(file_name.as_ref()).context(WhereIsJWK { origin: &origin })?;
+
+        Self::from_json_reader(std::io::BufReader::new(file), &origin)
+    }
 
-        let account: Self =
-            serde_json::from_reader(std::io::BufReader::new(file)).context(InvalidJWK {
-                file_name: file_name.as_ref(),
-            })?;
+    /// Read the JWK of the Service Account, such as the response of a secrets manager.
+    pub fn from_reader<R: std::io::Read>(reader: R) -> Result<Self> {
+        Self::from_json_reader(reader, "reader")
+    }
+
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let account = serde_json::from_str(json).context(InvalidJWK { origin: "string" })?;
+        Ok(account)
+    }
 
+    /// Read the JWK of the Service Account from the environment variable, which holds the JSON itself.
+    pub fn from_env(variable: &str) -> Result<Self> {
+        let json = std::env::var(variable).context(MissingJWKVariable { variable })?;
+
+        let origin = format!("environment variable {:?}", variable);
+        let account = serde_json::from_str(&json).context(InvalidJWK { origin })?;
         Ok(account)
     }
+
+    fn from_json_reader<R: std::io::Read>(reader: R, origin: &str) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(|source| {
+            // Failing to read is not the same as reading invalid JSON.
+            let error = match source.classify() {
+                serde_json::error::Category::Io => InnerError::WhereIsJWK {
+                    origin: origin.to_owned(),
+                    source: source.into(),
+                },
+                _ => InnerError::InvalidJWK {
+                    origin: origin.to_owned(),
+                    source,
+                },
+            };
+
+            Error(error)
+        })
+    }
+
+    /// The email address of the Service Account, such as for logging.
+    pub fn client_email(&self) -> &str {
+        &self.client_email
+    }
+
+    /// Act on behalf of a Google Workspace user through domain-wide delegation.
+    ///
+    /// The Service Account must be granted domain-wide delegation by a Workspace admin.
+    pub fn impersonate<S: Into<String>>(mut self, user_email: S) -> Self {
+        self.subject = Some(user_email.into());
+        self
+    }
 }
 
 // Test whether the readme example contains valid code.
@@ -0,0 +1,95 @@
+use crate::credentials::AccessToken;
+use crate::database::Pool;
+use chrono::{DateTime, Utc};
+use sqlx::Result;
+
+/// An access token stored in the database, so it survives restarts.
+pub(crate) struct CachedToken;
+
+impl CachedToken {
+    pub(crate) async fn get(
+        account: &str,
+        token_url: &str,
+        scope: &str,
+        pool: &Pool,
+    ) -> Result<Option<AccessToken>> {
+        match sqlx::query_as!(
+            AccessToken,
+            r#"
+            SELECT token, expiry as "expiry: DateTime<Utc>"
+            FROM access_tokens
+            WHERE account = $1 AND token_url = $2 AND scope = $3
+            "#,
+            account,
+            token_url,
+            scope,
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!("获取访问令牌失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) async fn upsert(
+        account: &str,
+        token_url: &str,
+        scope: &str,
+        token: &AccessToken,
+        pool: &Pool,
+    ) -> Result<()> {
+        match sqlx::query!(
+            "
+            INSERT OR REPLACE INTO access_tokens (account, token_url, scope, token, expiry)
+            VALUES ($1, $2, $3, $4, $5)
+            ",
+            account,
+            token_url,
+            scope,
+            token.token,
+            token.expiry,
+        )
+        .execute(pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("保存访问令牌失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove the token when it is still cached, as it has been rejected.
+    pub(crate) async fn delete(
+        account: &str,
+        token_url: &str,
+        scope: &str,
+        token: &str,
+        pool: &Pool,
+    ) -> Result<()> {
+        match sqlx::query!(
+            "
+            DELETE FROM access_tokens
+            WHERE account = $1 AND token_url = $2 AND scope = $3 AND token = $4
+            ",
+            account,
+            token_url,
+            scope,
+            token,
+        )
+        .execute(pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("删除访问令牌失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -1,21 +1,45 @@
 use crate::database::{Connection, Pool};
 
+/// ID of the virtual folder within My Drive, which holds the items shared with the user
+/// whose parents are not part of My Drive.
+/// It is derived from the drive ID, so the folders of different users do not share an ID.
+pub(crate) fn shared_with_me_id(drive_id: &str) -> String {
+    format!("{}:shared-with-me", drive_id)
+}
+
+/// Name of the virtual folder holding the items shared with the user.
+pub(crate) const SHARED_WITH_ME_NAME: &str = "Shared with me";
+
+/// What a drive consists of, which determines how it is listed and how its changes are fetched.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum Corpus {
+    /// A Shared Drive, whose ID is also the ID of its root folder.
+    Drive,
+    /// The My Drive of the user together with the items shared with the user.
+    /// The ID of the root folder of My Drive is used as drive ID.
+    User,
+}
+
 #[derive(Debug)]
 pub struct Drive {
     pub id: String,
     pub page_token: String,
+    pub corpus: Corpus,
 }
 
 impl Drive {
     pub(crate) async fn create(
         id: &str,
         page_token: &str,
+        corpus: Corpus,
         conn: &mut Connection,
     ) -> sqlx::Result<()> {
         match sqlx::query!(
-            "INSERT INTO drives (id, page_token) VALUES ($1, $2)",
+            "INSERT INTO drives (id, page_token, corpus) VALUES ($1, $2, $3)",
             id,
-            page_token
+            page_token,
+            corpus,
         )
         .execute(conn)
         .await
@@ -29,9 +53,13 @@ impl Drive {
     }
 
     pub(crate) async fn get_by_id(id: &str, pool: &Pool) -> sqlx::Result<Option<Self>> {
-        match sqlx::query_as!(Self, "SELECT * FROM drives WHERE id = $1", id)
-            .fetch_optional(pool)
-            .await
+        match sqlx::query_as!(
+            Self,
+            r#"SELECT id, page_token, corpus as "corpus: Corpus" FROM drives WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(pool)
+        .await
         {
             Ok(result) => Ok(result),
             Err(e) => {
@@ -41,6 +69,22 @@ impl Drive {
         }
     }
 
+    pub(crate) async fn get_all(pool: &Pool) -> sqlx::Result<Vec<Self>> {
+        match sqlx::query_as!(
+            Self,
+            r#"SELECT id, page_token, corpus as "corpus: Corpus" FROM drives"#
+        )
+        .fetch_all(pool)
+        .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!("获取所有驱动器失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
     pub(crate) async fn update_page_token(
         id: &str,
         page_token: &str,
@@ -61,4 +105,18 @@ impl Drive {
             }
         }
     }
+
+    /// Delete the drive together with all of its folders and files.
+    pub(crate) async fn delete(id: &str, conn: &mut Connection) -> sqlx::Result<()> {
+        match sqlx::query!("DELETE FROM drives WHERE id = $1", id)
+            .execute(conn)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("删除驱动器失败: {}", e);
+                Err(e)
+            }
+        }
+    }
 }
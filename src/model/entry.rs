@@ -0,0 +1,62 @@
+use super::{File, FileKind, Folder};
+
+/// A folder or file within a synchronised drive.
+#[derive(Debug)]
+pub enum Entry {
+    Folder(Folder),
+    File(File),
+}
+
+impl Entry {
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Folder(folder) => &folder.id,
+            Self::File(file) => &file.id,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Folder(folder) => &folder.name,
+            Self::File(file) => &file.name,
+        }
+    }
+}
+
+/// The metadata of a single folder or file.
+#[derive(Debug)]
+pub struct Stat {
+    pub id: String,
+    pub drive_id: String,
+    pub name: String,
+    /// The kind of the file, or `None` for folders.
+    pub kind: Option<FileKind>,
+    pub md5: Option<String>,
+    pub size: Option<i64>,
+    pub trashed: bool,
+}
+
+impl From<Entry> for Stat {
+    fn from(entry: Entry) -> Self {
+        match entry {
+            Entry::Folder(folder) => Self {
+                id: folder.id,
+                drive_id: folder.drive_id,
+                name: folder.name,
+                kind: None,
+                md5: None,
+                size: None,
+                trashed: folder.trashed,
+            },
+            Entry::File(file) => Self {
+                id: file.id,
+                drive_id: file.drive_id,
+                name: file.name,
+                kind: Some(file.kind),
+                md5: file.md5,
+                size: file.size,
+                trashed: file.trashed,
+            },
+        }
+    }
+}
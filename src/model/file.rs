@@ -1,83 +1,98 @@
+use super::parent::Parents;
 use crate::database::{Connection, Pool};
 use futures::prelude::*;
 use sqlx::Result;
 use tracing::trace;
 
+/// The kind of content a file holds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum FileKind {
+    /// A regular file with binary content, a checksum and a size.
+    Binary,
+    /// A native Google Workspace document, such as a Doc, Sheet, Slide or Form.
+    /// Documents do not have a checksum nor a size.
+    Document,
+    /// A Google Drive shortcut pointing to another file or folder.
+    Shortcut,
+}
+
 #[derive(Debug)]
 pub struct File {
     pub id: String,
     pub drive_id: String,
     pub name: String,
     pub trashed: bool,
-    pub parent: String,
-    pub md5: String,
-    pub size: i64,
+    pub(crate) parents: Vec<String>,
+    pub kind: FileKind,
+    pub mime_type: String,
+    pub md5: Option<String>,
+    pub size: Option<i64>,
+    /// ID of the file or folder a shortcut points to.
+    pub target_id: Option<String>,
+    /// MIME type of the file or folder a shortcut points to.
+    pub target_mime_type: Option<String>,
 }
 
 impl File {
-    pub(crate) async fn create(&self, conn: &mut Connection) -> Result<()> {
-        match sqlx::query!(
-            "
-            INSERT INTO files
-                (id, drive_id, name, trashed, parent, md5, size)
-            VALUES
-                ($1, $2, $3, $4, $5, $6, $7)
-            ",
-            self.id,
-            self.drive_id,
-            self.name,
-            self.trashed,
-            self.parent,
-            self.md5,
-            self.size
-        )
-        .execute(conn)
-        .await
-        {
-            Ok(_) => {
-                trace!(id = %self.id, "created file");
-                Ok(())
-            },
-            Err(e) => {
-                tracing::warn!("创建文件失败: {}", e);
-                Err(e)
-            }
+    /// The IDs of all parent folders. Every file has at least one parent.
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+
+    fn parent_links(&self) -> Parents<'_> {
+        Parents {
+            id: &self.id,
+            drive_id: &self.drive_id,
+            parents: &self.parents,
         }
     }
+
     pub(crate) async fn upsert(&self, conn: &mut Connection) -> Result<()> {
+        // Unlink the old parents before the update and link the new ones afterwards,
+        // so every row in the changelog reflects the file at the time of the change.
+        self.parent_links().unlink_stale(&mut *conn).await?;
+
         match sqlx::query!(
             "
             INSERT INTO files
-                (id, drive_id, name, trashed, parent, md5, size)
+                (id, drive_id, name, trashed, kind, mime_type, md5, size, target_id, target_mime_type)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7)
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             ON CONFLICT (id, drive_id) DO UPDATE SET
                 name = EXCLUDED.name,
                 trashed = EXCLUDED.trashed,
-                parent = EXCLUDED.parent,
+                kind = EXCLUDED.kind,
+                mime_type = EXCLUDED.mime_type,
                 md5 = EXCLUDED.md5,
-                size = EXCLUDED.size
+                size = EXCLUDED.size,
+                target_id = EXCLUDED.target_id,
+                target_mime_type = EXCLUDED.target_mime_type
             ",
             self.id,
             self.drive_id,
             self.name,
             self.trashed,
-            self.parent,
+            self.kind,
+            self.mime_type,
             self.md5,
-            self.size
+            self.size,
+            self.target_id,
+            self.target_mime_type
         )
-        .execute(conn)
+        .execute(&mut *conn)
         .await
         {
             Ok(_) => {
                 trace!(id = %self.id, "upserted file");
-                Ok(())
             },
             Err(e) => {
                 tracing::warn!("更新文件失败: {}", e);
-                Err(e)
+                return Err(e);
             }
         }
+
+        self.parent_links().link(conn).await
     }
 
     pub(crate) async fn delete(id: &str, drive_id: &str, conn: &mut Connection) -> Result<()> {
@@ -99,6 +114,135 @@ impl File {
             }
         }
     }
+
+    /// All files within the drive without any parent.
+    pub(crate) async fn get_orphans(drive_id: &str, conn: &mut Connection) -> Result<Vec<String>> {
+        match sqlx::query!(
+            r#"
+            SELECT f.id
+            FROM files f
+            WHERE f.drive_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM parents p
+                WHERE p.id = f.id AND p.drive_id = f.drive_id
+            )
+            "#,
+            drive_id
+        )
+        .fetch_all(conn)
+        .await
+        {
+            Ok(rows) => Ok(rows.into_iter().map(|row| row.id).collect()),
+            Err(e) => {
+                tracing::warn!("获取孤立文件失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
+
+struct FileRow {
+    pub id: String,
+    pub drive_id: String,
+    pub name: String,
+    pub trashed: bool,
+    pub parents: String,
+    pub kind: FileKind,
+    pub mime_type: String,
+    pub md5: Option<String>,
+    pub size: Option<i64>,
+    pub target_id: Option<String>,
+    pub target_mime_type: Option<String>,
+}
+
+impl From<FileRow> for File {
+    fn from(f: FileRow) -> Self {
+        Self {
+            id: f.id,
+            drive_id: f.drive_id,
+            name: f.name,
+            trashed: f.trashed,
+            // The parents are aggregated into a JSON array by SQLite.
+            parents: serde_json::from_str(&f.parents).unwrap_or_default(),
+            kind: f.kind,
+            mime_type: f.mime_type,
+            md5: f.md5,
+            size: f.size,
+            target_id: f.target_id,
+            target_mime_type: f.target_mime_type,
+        }
+    }
+}
+
+impl File {
+    pub(crate) async fn get_by_id(id: &str, drive_id: &str, pool: &Pool) -> Result<Option<Self>> {
+        match sqlx::query_as!(
+            FileRow,
+            r#"
+            SELECT
+                f.id, f.drive_id, f.name, f.trashed as "trashed!",
+                (
+                    SELECT json_group_array(p.parent) FROM parents p
+                    WHERE p.id = f.id AND p.drive_id = f.drive_id
+                ) as "parents!: String",
+                f.kind as "kind: FileKind", f.mime_type, f.md5, f.size,
+                f.target_id, f.target_mime_type
+            FROM files f
+            WHERE f.id = $1 AND f.drive_id = $2
+            "#,
+            id,
+            drive_id
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(result) => Ok(result.map(|f| f.into())),
+            Err(e) => {
+                tracing::warn!("获取文件失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// All files within the parent folder, optionally limited to the files with the provided name.
+    pub(crate) async fn get_by_parent(
+        parent_id: &str,
+        drive_id: &str,
+        name: Option<&str>,
+        pool: &Pool,
+    ) -> Result<Vec<Self>> {
+        match sqlx::query_as!(
+            FileRow,
+            r#"
+            SELECT
+                f.id, f.drive_id, f.name, f.trashed as "trashed!",
+                (
+                    SELECT json_group_array(p.parent) FROM parents p
+                    WHERE p.id = f.id AND p.drive_id = f.drive_id
+                ) as "parents!: String",
+                f.kind as "kind: FileKind", f.mime_type, f.md5, f.size,
+                f.target_id, f.target_mime_type
+            FROM files f
+            INNER JOIN parents l ON l.id = f.id AND l.drive_id = f.drive_id
+            WHERE l.parent = $1 AND l.drive_id = $2 AND ($3 IS NULL OR f.name = $3)
+            ORDER BY f.name
+            "#,
+            parent_id,
+            drive_id,
+            name
+        )
+        .fetch(pool)
+        .map_ok(|f| f.into())
+        .try_collect()
+        .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!("获取子文件失败: {}", e);
+                Err(e)
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -122,8 +266,12 @@ struct FileChangelog {
     pub name: String,
     pub trashed: bool,
     pub parent: String,
-    pub md5: String,
-    pub size: i64,
+    pub kind: FileKind,
+    pub mime_type: String,
+    pub md5: Option<String>,
+    pub size: Option<i64>,
+    pub target_id: Option<String>,
+    pub target_mime_type: Option<String>,
     pub deleted: bool,
 }
 
@@ -133,25 +281,46 @@ impl From<FileChangelog> for ChangedFile {
             id: f.id,
             drive_id: f.drive_id,
             name: f.name,
-            parent: f.parent,
+            parents: vec![f.parent],
             trashed: f.trashed,
+            kind: f.kind,
+            mime_type: f.mime_type,
             md5: f.md5,
             size: f.size,
+            target_id: f.target_id,
+            target_mime_type: f.target_mime_type,
         };
 
         match f.deleted {
-            true => Self::Created(file),
-            false => Self::Deleted(file),
+            true => Self::Deleted(file),
+            false => Self::Created(file),
         }
     }
 }
 
 impl ChangedFile {
-    pub(crate) async fn get_all(drive_id: &str, pool: &Pool) -> Result<Vec<Self>> {
+    /// The kind of the changed file, to tell binary files and documents apart.
+    pub fn kind(&self) -> FileKind {
+        match self {
+            Self::Created(file) => file.kind,
+            Self::Deleted(file) => file.kind,
+        }
+    }
+
+    /// The changed files of the drive in the generation.
+    pub(crate) async fn get_all(drive_id: &str, generation: i64, pool: &Pool) -> Result<Vec<Self>> {
         match sqlx::query_as!(
             FileChangelog,
-            "SELECT * FROM file_changelog WHERE drive_id = $1",
-            drive_id
+            r#"
+            SELECT
+                id, drive_id, name, trashed, parent,
+                kind as "kind: FileKind", mime_type, md5, size,
+                target_id, target_mime_type, deleted
+            FROM file_changelog
+            WHERE drive_id = $1 AND generation = $2
+            "#,
+            drive_id,
+            generation,
         )
         .fetch(pool)
         // Turn the FileChangelog into a ChangedFile
@@ -166,20 +335,4 @@ impl ChangedFile {
             }
         }
     }
-
-    pub(crate) async fn clear(drive_id: &str, pool: &Pool) -> Result<()> {
-        match sqlx::query!("DELETE FROM file_changelog WHERE drive_id = $1", drive_id)
-            .execute(pool)
-            .await
-        {
-            Ok(_) => {
-                trace!("cleared file changelog");
-                Ok(())
-            },
-            Err(e) => {
-                tracing::warn!("清除文件变更日志失败: {}", e);
-                Err(e)
-            }
-        }
-    }
 }
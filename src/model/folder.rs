@@ -1,3 +1,4 @@
+use super::parent::Parents;
 use crate::database::{Connection, Pool};
 use futures::prelude::*;
 use sqlx::Result;
@@ -9,68 +10,84 @@ pub struct Folder {
     pub drive_id: String,
     pub name: String,
     pub trashed: bool,
-    pub parent: Option<String>,
+    pub(crate) parents: Vec<String>,
 }
 
 impl Folder {
+    /// The IDs of all parent folders. Only the root folder of a drive has no parents.
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+
+    fn parent_links(&self) -> Parents<'_> {
+        Parents {
+            id: &self.id,
+            drive_id: &self.drive_id,
+            parents: &self.parents,
+        }
+    }
+
     pub(crate) async fn create(&self, conn: &mut Connection) -> Result<()> {
         match sqlx::query!(
             "
             INSERT INTO folders
-                (id, drive_id, name, trashed, parent)
+                (id, drive_id, name, trashed)
             VALUES
-                ($1, $2, $3, $4, $5)
+                ($1, $2, $3, $4)
             ",
             self.id,
             self.drive_id,
             self.name,
             self.trashed,
-            self.parent,
         )
-        .execute(conn)
+        .execute(&mut *conn)
         .await
         {
             Ok(_) => {
                 trace!(id = %self.id, "created folder");
-                Ok(())
             },
             Err(e) => {
                 tracing::warn!("创建文件夹失败: {}", e);
-                Err(e)
+                return Err(e);
             }
         }
+
+        self.parent_links().link(conn).await
     }
 
     pub(crate) async fn upsert(&self, conn: &mut Connection) -> Result<()> {
+        // Unlink the old parents before the update and link the new ones afterwards,
+        // so every row in the changelog reflects the folder at the time of the change.
+        self.parent_links().unlink_stale(&mut *conn).await?;
+
         match sqlx::query!(
             "
             INSERT INTO folders
-                (id, drive_id, name, trashed, parent)
+                (id, drive_id, name, trashed)
             VALUES
-                ($1, $2, $3, $4, $5)
+                ($1, $2, $3, $4)
             ON CONFLICT (id, drive_id) DO UPDATE SET
                 name = EXCLUDED.name,
-                trashed = EXCLUDED.trashed,
-                parent = EXCLUDED.parent
+                trashed = EXCLUDED.trashed
             ",
             self.id,
             self.drive_id,
             self.name,
             self.trashed,
-            self.parent,
         )
-        .execute(conn)
+        .execute(&mut *conn)
         .await
         {
             Ok(_) => {
                 trace!(id = %self.id, "upserted folder");
-                Ok(())
             },
             Err(e) => {
                 tracing::warn!("更新文件夹失败: {}", e);
-                Err(e)
+                return Err(e);
             }
         }
+
+        self.parent_links().link(conn).await
     }
 
     pub(crate) async fn delete(id: &str, drive_id: &str, conn: &mut Connection) -> Result<()> {
@@ -100,9 +117,10 @@ impl Folder {
     ) -> Result<Option<Vec<String>>> {
         match sqlx::query!(
             r#"
-            SELECT id
-            FROM folders
-            WHERE parent = $1 AND drive_id = $2
+            SELECT f.id
+            FROM folders f
+            INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+            WHERE p.parent = $1 AND p.drive_id = $2
             "#,
             parent_id,
             drive_id
@@ -127,6 +145,31 @@ impl Folder {
         }
     }
 
+    /// All folders within the drive, other than the root folder, without any parent.
+    pub(crate) async fn get_orphans(drive_id: &str, conn: &mut Connection) -> Result<Vec<String>> {
+        match sqlx::query!(
+            r#"
+            SELECT f.id
+            FROM folders f
+            WHERE f.drive_id = $1 AND f.id <> f.drive_id
+            AND NOT EXISTS (
+                SELECT 1 FROM parents p
+                WHERE p.id = f.id AND p.drive_id = f.drive_id
+            )
+            "#,
+            drive_id
+        )
+        .fetch_all(conn)
+        .await
+        {
+            Ok(rows) => Ok(rows.into_iter().map(|row| row.id).collect()),
+            Err(e) => {
+                tracing::warn!("获取孤立文件夹失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
     pub(crate) async fn update_name(
         id: &str,
         drive_id: &str,
@@ -154,6 +197,94 @@ impl Folder {
     }
 }
 
+struct FolderRow {
+    pub id: String,
+    pub drive_id: String,
+    pub name: String,
+    pub trashed: bool,
+    pub parents: String,
+}
+
+impl From<FolderRow> for Folder {
+    fn from(f: FolderRow) -> Self {
+        Self {
+            id: f.id,
+            drive_id: f.drive_id,
+            name: f.name,
+            trashed: f.trashed,
+            // The parents are aggregated into a JSON array by SQLite.
+            parents: serde_json::from_str(&f.parents).unwrap_or_default(),
+        }
+    }
+}
+
+impl Folder {
+    pub(crate) async fn get_by_id(id: &str, drive_id: &str, pool: &Pool) -> Result<Option<Self>> {
+        match sqlx::query_as!(
+            FolderRow,
+            r#"
+            SELECT
+                f.id, f.drive_id, f.name, f.trashed as "trashed!",
+                (
+                    SELECT json_group_array(p.parent) FROM parents p
+                    WHERE p.id = f.id AND p.drive_id = f.drive_id
+                ) as "parents!: String"
+            FROM folders f
+            WHERE f.id = $1 AND f.drive_id = $2
+            "#,
+            id,
+            drive_id
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(result) => Ok(result.map(|f| f.into())),
+            Err(e) => {
+                tracing::warn!("获取文件夹失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// All folders within the parent folder, optionally limited to the folders with the provided name.
+    pub(crate) async fn get_by_parent(
+        parent_id: &str,
+        drive_id: &str,
+        name: Option<&str>,
+        pool: &Pool,
+    ) -> Result<Vec<Self>> {
+        match sqlx::query_as!(
+            FolderRow,
+            r#"
+            SELECT
+                f.id, f.drive_id, f.name, f.trashed as "trashed!",
+                (
+                    SELECT json_group_array(p.parent) FROM parents p
+                    WHERE p.id = f.id AND p.drive_id = f.drive_id
+                ) as "parents!: String"
+            FROM folders f
+            INNER JOIN parents l ON l.id = f.id AND l.drive_id = f.drive_id
+            WHERE l.parent = $1 AND l.drive_id = $2 AND ($3 IS NULL OR f.name = $3)
+            ORDER BY f.name
+            "#,
+            parent_id,
+            drive_id,
+            name
+        )
+        .fetch(pool)
+        .map_ok(|f| f.into())
+        .try_collect()
+        .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!("获取子文件夹失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ChangedFolder {
     Created(Folder),
@@ -174,7 +305,7 @@ struct FolderChangelog {
     pub drive_id: String,
     pub name: String,
     pub trashed: bool,
-    pub parent: Option<String>,
+    pub parent: String,
     pub deleted: bool,
 }
 
@@ -184,23 +315,28 @@ impl From<FolderChangelog> for ChangedFolder {
             id: f.id,
             drive_id: f.drive_id,
             name: f.name,
-            parent: f.parent,
+            parents: vec![f.parent],
             trashed: f.trashed,
         };
 
         match f.deleted {
-            true => Self::Created(folder),
-            false => Self::Deleted(folder),
+            true => Self::Deleted(folder),
+            false => Self::Created(folder),
         }
     }
 }
 
 impl ChangedFolder {
-    pub(crate) async fn get_all(drive_id: &str, pool: &Pool) -> Result<Vec<Self>> {
+    /// The changed folders of the drive in the generation.
+    pub(crate) async fn get_all(drive_id: &str, generation: i64, pool: &Pool) -> Result<Vec<Self>> {
         match sqlx::query_as!(
             FolderChangelog,
-            "SELECT * FROM folder_changelog WHERE drive_id = $1",
-            drive_id
+            "
+            SELECT id, drive_id, name, trashed, parent, deleted FROM folder_changelog
+            WHERE drive_id = $1 AND generation = $2
+            ",
+            drive_id,
+            generation,
         )
         .fetch(pool)
         // Turn the FolderChangelog into a ChangedFolder
@@ -215,20 +351,4 @@ impl ChangedFolder {
             }
         }
     }
-
-    pub(crate) async fn clear(drive_id: &str, pool: &Pool) -> Result<()> {
-        match sqlx::query!("DELETE FROM folder_changelog WHERE drive_id = $1", drive_id)
-            .execute(pool)
-            .await
-        {
-            Ok(_) => {
-                trace!("cleared folder changelog");
-                Ok(())
-            },
-            Err(e) => {
-                tracing::warn!("清除文件夹变更日志失败: {}", e);
-                Err(e)
-            }
-        }
-    }
 }
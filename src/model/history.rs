@@ -0,0 +1,309 @@
+use super::path::PathChangelog;
+use crate::database::{Connection, Pool};
+use crate::model::{ChangedFile, ChangedFolder, ChangedPath};
+use sqlx::Result;
+use std::collections::BTreeMap;
+use tracing::trace;
+
+/// The changes of a single synchronisation, see [`Bernard::changes_since`](crate::Bernard::changes_since).
+#[derive(Debug)]
+pub struct ChangeSet {
+    /// Increases with every synchronisation which changed anything, across all drives.
+    pub generation: i64,
+    pub paths: Vec<ChangedPath>,
+    pub folders: Vec<ChangedFolder>,
+    pub files: Vec<ChangedFile>,
+}
+
+/// The changes of past synchronisations, which are kept until every consumer has acknowledged them.
+///
+/// The changelog rows of a synchronisation are tagged with its generation, and its paths are kept
+/// in `path_history` as they were resolved at the time.
+pub(crate) struct History;
+
+impl History {
+    /// Tag the rows the current synchronisation has written to the changelog with a new generation,
+    /// unless nothing has changed, and prune the generations no consumer is waiting for.
+    pub(crate) async fn record(drive_id: &str, conn: &mut Connection) -> Result<Option<i64>> {
+        // The triggers write generation 0.
+        let changed = match sqlx::query!(
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM folder_changelog WHERE drive_id = $1 AND generation = 0)
+                OR EXISTS (SELECT 1 FROM file_changelog WHERE drive_id = $1 AND generation = 0)
+                as "changed!: bool"
+            "#,
+            drive_id,
+        )
+        .fetch_one(&mut *conn)
+        .await
+        {
+            Ok(result) => result.changed,
+            Err(e) => {
+                tracing::warn!("检查变更日志失败: {}", e);
+                return Err(e);
+            }
+        };
+
+        if !changed {
+            return Ok(None);
+        }
+
+        let generation = match sqlx::query!(
+            "INSERT INTO generations (drive_id) VALUES ($1)",
+            drive_id
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            Ok(result) => result.last_insert_rowid(),
+            Err(e) => {
+                tracing::warn!("创建变更代失败: {}", e);
+                return Err(e);
+            }
+        };
+
+        // Resolved before the rows are tagged, as the view only joins rows of the same generation.
+        let query = r#"
+            INSERT INTO path_history (generation, id, drive_id, kind, deleted, trashed, path)
+            SELECT $1, id, drive_id, kind, deleted, trashed, path FROM path_changelog
+            WHERE drive_id = $2 AND generation = 0
+        "#;
+
+        let recorded = match sqlx::query(query)
+            .bind(generation)
+            .bind(drive_id)
+            .execute(&mut *conn)
+            .await
+        {
+            Ok(result) => result.rows_affected(),
+            Err(e) => {
+                tracing::warn!("保存路径历史失败: {}", e);
+                return Err(e);
+            }
+        };
+
+        for changelog in &["folder_changelog", "file_changelog"] {
+            let query = format!(
+                "UPDATE {} SET generation = $1 WHERE drive_id = $2 AND generation = 0",
+                changelog
+            );
+
+            if let Err(e) = sqlx::query(&query)
+                .bind(generation)
+                .bind(drive_id)
+                .execute(&mut *conn)
+                .await
+            {
+                tracing::warn!("标记变更日志失败: {}", e);
+                return Err(e);
+            }
+        }
+
+        trace!(generation, count = recorded, "recorded path history");
+
+        Self::prune_before(drive_id, generation, conn).await?;
+        Ok(Some(generation))
+    }
+
+    /// Remove the earlier generations of the drive which every consumer has acknowledged,
+    /// or all of them when the drive has no consumers, so the history does not grow unbounded.
+    async fn prune_before(drive_id: &str, generation: i64, conn: &mut Connection) -> Result<()> {
+        match sqlx::query!(
+            "
+            DELETE FROM generations
+            WHERE drive_id = $1 AND generation < $2
+            AND generation <= COALESCE(
+                (SELECT MIN(generation) FROM consumers WHERE drive_id = $1),
+                $2
+            )
+            ",
+            drive_id,
+            generation,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(result) => {
+                trace!(count = result.rows_affected(), "pruned path history");
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("清理路径历史失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// The retained changes of the drive after the provided generation, oldest first.
+    pub(crate) async fn get_since(
+        drive_id: &str,
+        generation: i64,
+        pool: &Pool,
+    ) -> Result<Vec<ChangeSet>> {
+        let generations = match sqlx::query!(
+            "
+            SELECT generation FROM generations
+            WHERE drive_id = $1 AND generation > $2
+            ORDER BY generation
+            ",
+            drive_id,
+            generation,
+        )
+        .fetch_all(pool)
+        .await
+        {
+            Ok(rows) => rows.into_iter().map(|row| row.generation),
+            Err(e) => {
+                tracing::warn!("获取变更代失败: {}", e);
+                return Err(e);
+            }
+        };
+
+        let query = r#"
+            SELECT generation, id, drive_id, kind, deleted, trashed, path FROM path_history
+            WHERE drive_id = $1 AND generation > $2
+        "#;
+
+        let rows = match sqlx::query_as::<_, PathChangelog>(query)
+            .bind(drive_id)
+            .bind(generation)
+            .fetch_all(pool)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::warn!("获取路径历史失败: {}", e);
+                return Err(e);
+            }
+        };
+
+        let mut paths: BTreeMap<i64, Vec<PathChangelog>> = BTreeMap::new();
+        for row in rows {
+            paths.entry(row.generation).or_default().push(row);
+        }
+
+        let mut sets = Vec::new();
+        for generation in generations {
+            sets.push(ChangeSet {
+                generation,
+                paths: ChangedPath::from_changelogs(paths.remove(&generation).unwrap_or_default()),
+                folders: ChangedFolder::get_all(drive_id, generation, pool).await?,
+                files: ChangedFile::get_all(drive_id, generation, pool).await?,
+            });
+        }
+
+        Ok(sets)
+    }
+
+    /// The last generation the consumer has acknowledged for the drive.
+    pub(crate) async fn get_cursor(
+        consumer: &str,
+        drive_id: &str,
+        pool: &Pool,
+    ) -> Result<Option<i64>> {
+        match sqlx::query!(
+            "SELECT generation FROM consumers WHERE consumer = $1 AND drive_id = $2",
+            consumer,
+            drive_id,
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(result) => Ok(result.map(|row| row.generation)),
+            Err(e) => {
+                tracing::warn!("获取消费者游标失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Start retaining the changes of the drive for the consumer, unless it is known already.
+    pub(crate) async fn register(consumer: &str, drive_id: &str, pool: &Pool) -> Result<()> {
+        match sqlx::query!(
+            "
+            INSERT INTO consumers (consumer, drive_id, generation) VALUES ($1, $2, 0)
+            ON CONFLICT (consumer, drive_id) DO NOTHING
+            ",
+            consumer,
+            drive_id,
+        )
+        .execute(pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("注册消费者失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Move the cursor of the consumer forward, it never moves backwards.
+    pub(crate) async fn acknowledge(
+        consumer: &str,
+        drive_id: &str,
+        generation: i64,
+        pool: &Pool,
+    ) -> Result<()> {
+        match sqlx::query!(
+            "
+            INSERT INTO consumers (consumer, drive_id, generation) VALUES ($1, $2, $3)
+            ON CONFLICT (consumer, drive_id) DO UPDATE SET generation = MAX(generation, excluded.generation)
+            ",
+            consumer,
+            drive_id,
+            generation,
+        )
+        .execute(pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("确认变更失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) async fn remove_consumer(consumer: &str, pool: &Pool) -> Result<()> {
+        match sqlx::query!("DELETE FROM consumers WHERE consumer = $1", consumer)
+            .execute(pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("删除消费者失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove the generations of the drive which every consumer has acknowledged,
+    /// or all generations when the drive has no consumers.
+    pub(crate) async fn prune(drive_id: &str, pool: &Pool) -> Result<u64> {
+        match sqlx::query!(
+            "
+            DELETE FROM generations
+            WHERE drive_id = $1
+            AND generation <= COALESCE(
+                (SELECT MIN(generation) FROM consumers WHERE drive_id = $1),
+                (SELECT MAX(generation) FROM generations WHERE drive_id = $1)
+            )
+            ",
+            drive_id,
+        )
+        .execute(pool)
+        .await
+        {
+            Ok(result) => {
+                trace!(count = result.rows_affected(), "pruned path history");
+                Ok(result.rows_affected())
+            }
+            Err(e) => {
+                tracing::warn!("清理路径历史失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -1,9 +1,23 @@
+mod access_token;
 mod drive;
+mod entry;
 mod file;
 mod folder;
+mod history;
+mod parent;
 mod path;
+mod staging;
+mod sync_run;
 
-pub use drive::Drive;
-pub use file::{ChangedFile, File};
+pub(crate) use access_token::CachedToken;
+pub use drive::{Corpus, Drive};
+pub(crate) use drive::{shared_with_me_id, SHARED_WITH_ME_NAME};
+pub use entry::{Entry, Stat};
+pub use file::{ChangedFile, File, FileKind};
 pub use folder::{ChangedFolder, Folder};
-pub use path::{ChangedPath, InnerPath, Path};
+pub use history::ChangeSet;
+pub(crate) use history::History;
+pub(crate) use parent::Parents;
+pub use path::{ChangedPath, InnerPath, Path, ShortcutPolicy};
+pub(crate) use staging::StagedDrive;
+pub use sync_run::{ItemCounts, RunKind, SyncRun};
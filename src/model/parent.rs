@@ -0,0 +1,136 @@
+use crate::database::Connection;
+use sqlx::Result;
+use tracing::trace;
+
+/// The parents of a single folder or file, stored in the `parents` join table.
+///
+/// The changelog is written by the triggers on the join table,
+/// so an item must exist before its parents are linked.
+pub(crate) struct Parents<'a> {
+    pub id: &'a str,
+    pub drive_id: &'a str,
+    pub parents: &'a [String],
+}
+
+impl<'a> Parents<'a> {
+    /// Link every parent which is not linked yet.
+    pub(crate) async fn link(&self, conn: &mut Connection) -> Result<()> {
+        for parent in self.parents {
+            match sqlx::query!(
+                "INSERT OR IGNORE INTO parents (id, drive_id, parent) VALUES ($1, $2, $3)",
+                self.id,
+                self.drive_id,
+                parent,
+            )
+            .execute(&mut *conn)
+            .await
+            {
+                Ok(_) => trace!(id = %self.id, %parent, "linked parent"),
+                Err(e) => {
+                    tracing::warn!("关联父文件夹失败: {}", e);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unlink every parent which is no longer one of the parents.
+    pub(crate) async fn unlink_stale(&self, conn: &mut Connection) -> Result<()> {
+        let parents = serde_json::to_string(self.parents).unwrap();
+
+        match sqlx::query!(
+            "
+            DELETE FROM parents
+            WHERE id = $1 AND drive_id = $2
+            AND parent NOT IN (SELECT value FROM json_each($3))
+            ",
+            self.id,
+            self.drive_id,
+            parents,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(_) => {
+                trace!(id = %self.id, "unlinked stale parents");
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("取消关联父文件夹失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Unlink all parents which do not exist within the drive.
+    pub(crate) async fn unlink_dangling(drive_id: &str, conn: &mut Connection) -> Result<()> {
+        match sqlx::query!(
+            "
+            DELETE FROM parents
+            WHERE drive_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM folders f
+                WHERE f.id = parents.parent AND f.drive_id = parents.drive_id
+            )
+            ",
+            drive_id,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(result) => {
+                trace!(count = %result.rows_affected(), "unlinked dangling parents");
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("取消关联不存在的父文件夹失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Link every item without any existing parent to the provided folder instead,
+    /// limited to the provided items if any.
+    ///
+    /// Used for the items shared with the user, whose parents are not part of My Drive.
+    pub(crate) async fn adopt_dangling(
+        drive_id: &str,
+        folder_id: &str,
+        ids: Option<&[String]>,
+        conn: &mut Connection,
+    ) -> Result<()> {
+        let ids = ids.map(|ids| serde_json::to_string(ids).unwrap());
+
+        match sqlx::query!(
+            "
+            INSERT OR IGNORE INTO parents (id, drive_id, parent)
+            SELECT DISTINCT p.id, p.drive_id, $2
+            FROM parents p
+            WHERE p.drive_id = $1
+            AND ($3 IS NULL OR p.id IN (SELECT value FROM json_each($3)))
+            AND NOT EXISTS (
+                SELECT 1 FROM parents q
+                JOIN folders f ON f.id = q.parent AND f.drive_id = q.drive_id
+                WHERE q.id = p.id AND q.drive_id = p.drive_id
+            )
+            ",
+            drive_id,
+            folder_id,
+            ids,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(result) => {
+                trace!(count = %result.rows_affected(), "adopted items with dangling parents");
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("收养无父文件夹的项目失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
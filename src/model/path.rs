@@ -1,13 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
 use std::path::PathBuf;
 
-use crate::database::Pool;
+use crate::database::{Connection, Pool};
 use futures::prelude::*;
 
 #[derive(Debug)]
 pub enum Path {
     File(InnerPath),
     Folder(InnerPath),
+    /// A native Google Workspace document without binary content.
+    Document(InnerPath),
+    /// A shortcut exposed as a link, see [`ShortcutPolicy::Link`].
+    Shortcut(InnerPath),
 }
 
 impl Path {
@@ -15,10 +19,23 @@ impl Path {
         match self {
             Self::File(inner) => inner.trashed,
             Self::Folder(inner) => inner.trashed,
+            Self::Document(inner) => inner.trashed,
+            Self::Shortcut(inner) => inner.trashed,
         }
     }
 }
 
+/// How shortcuts are exposed when reading the paths of a Shared Drive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ShortcutPolicy {
+    /// Expose every shortcut as a [`Path::Shortcut`] at its own location.
+    #[default]
+    Link,
+    /// Replace every shortcut by a copy of its target, including all descendants when the target is a folder.
+    /// Shortcuts to a target which has not been synchronised are still exposed as links.
+    Follow,
+}
+
 #[derive(Debug, sqlx::FromRow)]
 pub struct InnerPath {
     pub id: String,
@@ -31,23 +48,27 @@ pub struct InnerPath {
 pub enum ChangedPath {
     Created(Path),
     Deleted(Path),
+    /// The folder or file has been moved to another folder, possibly under another name.
+    Moved { from: Path, to: Path },
+    /// The folder or file has been renamed within the same folder.
+    Renamed { from: Path, to: Path },
 }
 
 impl From<ChangedPath> for Path {
+    /// The current path, or the former path of a deleted folder or file.
     fn from(path: ChangedPath) -> Self {
         match path {
             ChangedPath::Created(path) => path,
             ChangedPath::Deleted(path) => path,
+            ChangedPath::Moved { to, .. } => to,
+            ChangedPath::Renamed { to, .. } => to,
         }
     }
 }
 
 impl From<ChangedPath> for InnerPath {
     fn from(path: ChangedPath) -> Self {
-        match path {
-            ChangedPath::Created(path) => path.into(),
-            ChangedPath::Deleted(path) => path.into(),
-        }
+        Path::from(path).into()
     }
 }
 
@@ -56,16 +77,149 @@ impl From<Path> for InnerPath {
         match path {
             Path::File(inner) => inner,
             Path::Folder(inner) => inner,
+            Path::Document(inner) => inner,
+            Path::Shortcut(inner) => inner,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub(super) enum PathKind {
+    Folder,
+    Binary,
+    Document,
+    Shortcut,
+}
+
+impl PathKind {
+    fn into_path(self, inner_path: InnerPath) -> Path {
+        match self {
+            Self::Folder => Path::Folder(inner_path),
+            Self::Binary => Path::File(inner_path),
+            Self::Document => Path::Document(inner_path),
+            Self::Shortcut => Path::Shortcut(inner_path),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PathRow {
+    pub id: String,
+    pub drive_id: String,
+    pub path: String,
+    pub kind: PathKind,
+    pub trashed: bool,
+}
+
+impl PathRow {
+    /// The same folder or file at another path.
+    fn with_path(&self, path: String, trashed: bool) -> Path {
+        let inner_path = InnerPath {
+            id: self.id.clone(),
+            drive_id: self.drive_id.clone(),
+            path: path.into(),
+            trashed,
+        };
+
+        self.kind.into_path(inner_path)
+    }
+}
+
+impl From<PathRow> for Path {
+    fn from(p: PathRow) -> Self {
+        let inner_path = InnerPath {
+            id: p.id,
+            drive_id: p.drive_id,
+            path: p.path.into(),
+            trashed: p.trashed,
+        };
+
+        p.kind.into_path(inner_path)
+    }
+}
+
+impl Path {
+    pub(crate) fn stream_all<'a>(
+        drive_id: &'a str,
+        policy: ShortcutPolicy,
+        pool: &'a Pool,
+    ) -> impl Stream<Item = sqlx::Result<Self>> + 'a {
+        // The query macros of sqlx cannot describe recursive views and CTEs, so these queries are checked at runtime.
+        let query = match policy {
+            ShortcutPolicy::Link => "SELECT * FROM paths WHERE drive_id = $1",
+            ShortcutPolicy::Follow => "SELECT * FROM resolved_paths WHERE drive_id = $1",
+        };
+
+        sqlx::query_as::<_, PathRow>(query)
+            .bind(drive_id)
+            .fetch(pool)
+            .map_ok(|p| p.into())
+            .inspect_err(|e| tracing::warn!("获取路径失败: {}", e))
+    }
+
+    pub(crate) async fn get_all(
+        drive_id: &str,
+        policy: ShortcutPolicy,
+        pool: &Pool,
+    ) -> sqlx::Result<Vec<Self>> {
+        Self::stream_all(drive_id, policy, pool).try_collect().await
+    }
+
+    /// All paths of a single folder or file, one for every parent.
+    pub(crate) async fn get_by_id(
+        id: &str,
+        drive_id: &str,
+        pool: &Pool,
+    ) -> sqlx::Result<Vec<Self>> {
+        // Same as the `paths` view, but walks up from a single item.
+        let query = r#"
+            WITH RECURSIVE item_paths AS (
+                SELECT 'folder' as 'kind', f.id, f.drive_id, p.parent, f.trashed, '/' || f.name as path
+                FROM folders f
+                INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+                WHERE f.id = $1 AND f.drive_id = $2
+
+                UNION ALL
+
+                SELECT f.kind, f.id, f.drive_id, p.parent, f.trashed, '/' || f.name as path
+                FROM files f
+                INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+                WHERE f.id = $1 AND f.drive_id = $2
+
+                UNION ALL
+
+                SELECT i.kind, i.id, f.drive_id, p.parent, i.trashed, '/' || f.name || i.path as path
+                FROM item_paths i
+                INNER JOIN folders f ON f.id = i.parent AND f.drive_id = i.drive_id
+                INNER JOIN parents p ON p.id = f.id AND p.drive_id = f.drive_id
+            )
+            SELECT i.kind, i.id, i.drive_id, i.trashed, i.path FROM item_paths i
+            WHERE i.drive_id = i.parent
+        "#;
+
+        match sqlx::query_as::<_, PathRow>(query)
+            .bind(id)
+            .bind(drive_id)
+            .fetch_all(pool)
+            .await
+        {
+            Ok(rows) => Ok(rows.into_iter().map(|p| p.into()).collect()),
+            Err(e) => {
+                tracing::warn!("获取路径失败: {}", e);
+                Err(e)
+            }
         }
     }
 }
 
 #[derive(sqlx::FromRow)]
-struct PathChangelog {
+pub(super) struct PathChangelog {
+    pub generation: i64,
     pub id: String,
     pub drive_id: String,
     pub path: String,
-    pub folder: bool,
+    pub kind: PathKind,
     pub deleted: bool,
     pub trashed: bool,
 }
@@ -79,47 +233,256 @@ impl From<PathChangelog> for Path {
             trashed: p.trashed,
         };
 
-        match p.folder {
-            true => Path::Folder(inner_path),
-            false => Path::File(inner_path),
-        }
+        p.kind.into_path(inner_path)
     }
 }
 
-impl From<PathChangelog> for ChangedPath {
-    fn from(path: PathChangelog) -> Self {
-        match path.deleted {
-            true => Self::Deleted(path.into()),
-            false => Self::Created(path.into()),
-        }
-    }
+/// A folder whose descendants have changed paths without changes of their own.
+enum Expansion {
+    /// The folder has been moved or renamed.
+    Move { id: String, from: String, to: String },
+    /// The folder has been trashed or untrashed in place.
+    Trash { id: String, path: String, trashed: bool },
 }
 
 impl ChangedPath {
-    pub(crate) async fn get_all(drive_id: &str, pool: &Pool) -> sqlx::Result<Vec<Self>> {
-        let path_changelogs: Vec<PathChangelog> = match sqlx::query_as::<_, PathChangelog>(
-            "SELECT * FROM path_changelog WHERE drive_id = $1"
+    /// The changed paths of the drive in the generation, where the deleted and created paths
+    /// of the same folder or file are paired into moves and renames.
+    pub(crate) async fn get_all(
+        drive_id: &str,
+        generation: i64,
+        conn: &mut Connection,
+    ) -> sqlx::Result<Vec<Self>> {
+        let changelogs = Self::get_changelog(drive_id, generation, conn).await?;
+        Ok(Self::from_changelogs(changelogs))
+    }
+
+    /// Pair the deleted and created paths of every folder and file.
+    pub(super) fn from_changelogs(changelogs: Vec<PathChangelog>) -> Vec<Self> {
+        let mut changes: Vec<Self> = Self::group(changelogs)
+            .into_values()
+            .flat_map(|(deleted, created)| Self::pair(deleted, created))
+            .collect();
+
+        changes.sort_by(|a, b| a.current_path().cmp(b.current_path()));
+        changes
+    }
+
+    /// Same as [`ChangedPath::get_all`], but every folder which has been moved, renamed, trashed
+    /// or untrashed is expanded into the paths of its descendants.
+    ///
+    /// The descendants are read from the current tree, so `conn` should be a transaction.
+    pub(crate) async fn get_all_with_descendants(
+        drive_id: &str,
+        generation: i64,
+        conn: &mut Connection,
+    ) -> sqlx::Result<Vec<Self>> {
+        let by_id = Self::group(Self::get_changelog(drive_id, generation, conn).await?);
+        let changed: HashSet<String> = by_id.keys().cloned().collect();
+
+        let mut changes = Vec::new();
+        let mut expansions = Vec::new();
+        for (id, (deleted, created)) in by_id {
+            for to in created.iter().filter(|c| c.kind == PathKind::Folder) {
+                let transition = deleted
+                    .iter()
+                    .any(|from| from.path == to.path && from.trashed != to.trashed);
+
+                if transition {
+                    expansions.push(Expansion::Trash {
+                        id: id.clone(),
+                        path: to.path.clone(),
+                        trashed: to.trashed,
+                    });
+                }
+            }
+
+            for change in Self::pair(deleted, created) {
+                match &change {
+                    Self::Moved {
+                        from: Path::Folder(from),
+                        to: Path::Folder(to),
+                    }
+                    | Self::Renamed {
+                        from: Path::Folder(from),
+                        to: Path::Folder(to),
+                    } => expansions.push(Expansion::Move {
+                        id: id.clone(),
+                        from: from.path.to_string_lossy().into_owned(),
+                        to: to.path.to_string_lossy().into_owned(),
+                    }),
+                    _ => {}
+                }
+
+                changes.push(change);
+            }
+        }
+
+        // Folders and files with changes of their own are already reported, including their descendants.
+        let changed_json = serde_json::to_string(&changed).unwrap();
+        for expansion in expansions {
+            let id = match &expansion {
+                Expansion::Move { id, .. } | Expansion::Trash { id, .. } => id,
+            };
+
+            for row in Self::get_descendants(id, drive_id, &changed_json, conn).await? {
+                if changed.contains(&row.id) {
+                    continue;
+                }
+
+                changes.push(match &expansion {
+                    Expansion::Move { from, to, .. } => Self::Moved {
+                        from: row.with_path(format!("{}{}", from, row.path), row.trashed),
+                        to: row.with_path(format!("{}{}", to, row.path), row.trashed),
+                    },
+                    Expansion::Trash { path, trashed, .. } => Self::Created(
+                        row.with_path(format!("{}{}", path, row.path), *trashed || row.trashed),
+                    ),
+                });
+            }
+        }
+
+        changes.sort_by(|a, b| a.current_path().cmp(b.current_path()));
+        Ok(changes)
+    }
+
+    async fn get_changelog(
+        drive_id: &str,
+        generation: i64,
+        conn: &mut Connection,
+    ) -> sqlx::Result<Vec<PathChangelog>> {
+        match sqlx::query_as::<_, PathChangelog>(
+            "SELECT * FROM path_changelog WHERE drive_id = $1 AND generation = $2"
         )
             .bind(drive_id)
-            .fetch_all(pool)
+            .bind(generation)
+            .fetch_all(conn)
             .await
         {
-            Ok(changelogs) => changelogs,
+            Ok(changelogs) => Ok(changelogs),
             Err(e) => {
                 tracing::warn!("获取路径变更日志失败: {}", e);
-                return Err(e);
+                Err(e)
             }
-        };
+        }
+    }
 
-        // 使用 HashMap 来去除重复项，保留最新的变更
-        let mut unique_changes: HashMap<String, PathChangelog> = HashMap::new();
+    /// The deleted and created paths of every changed folder and file.
+    fn group(
+        path_changelogs: Vec<PathChangelog>,
+    ) -> BTreeMap<String, (Vec<PathChangelog>, Vec<PathChangelog>)> {
+        let mut by_id: BTreeMap<String, (Vec<PathChangelog>, Vec<PathChangelog>)> =
+            BTreeMap::new();
         for changelog in path_changelogs {
-            unique_changes.insert(changelog.path.clone(), changelog);
+            let (deleted, created) = by_id.entry(changelog.id.clone()).or_default();
+
+            match changelog.deleted {
+                true => deleted.push(changelog),
+                false => created.push(changelog),
+            }
         }
 
-        // 转换为 ChangedPath 并收集结果
-        Ok(unique_changes.into_values()
-            .map(|p| p.into())
-            .collect())
+        by_id
+    }
+
+    /// The paths of all descendants of a folder relative to the folder,
+    /// without descending into the folders in `changed`, a JSON array of ids.
+    async fn get_descendants(
+        id: &str,
+        drive_id: &str,
+        changed: &str,
+        conn: &mut Connection,
+    ) -> sqlx::Result<Vec<PathRow>> {
+        let query = r#"
+            WITH RECURSIVE items AS (
+                SELECT 'folder' as 'kind', id, drive_id, name, trashed FROM folders
+                UNION ALL
+                SELECT kind, id, drive_id, name, trashed FROM files
+            ),
+            descendants AS (
+                SELECT i.kind, i.id, i.drive_id, i.trashed, '/' || i.name as path
+                FROM parents p
+                INNER JOIN items i ON i.id = p.id AND i.drive_id = p.drive_id
+                WHERE p.parent = $1 AND p.drive_id = $2
+
+                UNION ALL
+
+                SELECT i.kind, i.id, i.drive_id, i.trashed, d.path || '/' || i.name as path
+                FROM descendants d
+                INNER JOIN parents p ON p.parent = d.id AND p.drive_id = d.drive_id
+                INNER JOIN items i ON i.id = p.id AND i.drive_id = p.drive_id
+                WHERE d.kind = 'folder' AND d.id NOT IN (SELECT value FROM json_each($3))
+            )
+            SELECT d.kind, d.id, d.drive_id, d.trashed, d.path FROM descendants d
+        "#;
+
+        match sqlx::query_as::<_, PathRow>(query)
+            .bind(id)
+            .bind(drive_id)
+            .bind(changed)
+            .fetch_all(conn)
+            .await
+        {
+            Ok(rows) => Ok(rows),
+            Err(e) => {
+                tracing::warn!("获取子路径失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Pair the deleted and created paths of a single folder or file.
+    fn pair(mut deleted: Vec<PathChangelog>, mut created: Vec<PathChangelog>) -> Vec<Self> {
+        deleted.sort_by(|a, b| a.path.cmp(&b.path));
+        created.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // An update in place, such as new content, is reported as created.
+        deleted.retain(|d| !created.iter().any(|c| c.path == d.path));
+
+        let mut changes = Vec::new();
+        let mut moved_from = Vec::new();
+
+        // Renames first, so an item with multiple parents is not reported as moved between them.
+        for from in deleted {
+            let parent = std::path::Path::new(&from.path).parent();
+            let renamed = created
+                .iter()
+                .position(|to| std::path::Path::new(&to.path).parent() == parent);
+
+            match renamed {
+                Some(index) => changes.push(Self::Renamed {
+                    from: from.into(),
+                    to: created.remove(index).into(),
+                }),
+                None => moved_from.push(from),
+            }
+        }
+
+        let mut created = created.into_iter();
+        for from in moved_from {
+            match created.next() {
+                Some(to) => changes.push(Self::Moved {
+                    from: from.into(),
+                    to: to.into(),
+                }),
+                None => changes.push(Self::Deleted(from.into())),
+            }
+        }
+
+        changes.extend(created.map(|to| Self::Created(to.into())));
+        changes
+    }
+
+    /// The current path, or the former path of a deleted folder or file.
+    fn current_path(&self) -> &std::path::Path {
+        let path = match self {
+            Self::Created(path) | Self::Deleted(path) => path,
+            Self::Moved { to, .. } | Self::Renamed { to, .. } => to,
+        };
+
+        match path {
+            Path::File(inner) | Path::Folder(inner) => &inner.path,
+            Path::Document(inner) | Path::Shortcut(inner) => &inner.path,
+        }
     }
 }
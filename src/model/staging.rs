@@ -0,0 +1,300 @@
+use super::{Corpus, File, Folder};
+use crate::database::{Connection, Pool};
+use sqlx::Result;
+use tracing::trace;
+
+/// A drive whose full synchronisation has not been promoted yet.
+///
+/// The pages of the listing are committed to the staging tables one at a time,
+/// so an interrupted synchronisation can resume from the last completed page.
+#[derive(Debug)]
+pub(crate) struct StagedDrive {
+    pub id: String,
+    pub name: String,
+    pub page_token: String,
+    pub next_page_token: Option<String>,
+    pub complete: bool,
+    pub corpus: Corpus,
+}
+
+impl StagedDrive {
+    pub(crate) async fn create(
+        id: &str,
+        name: &str,
+        page_token: &str,
+        corpus: Corpus,
+        conn: &mut Connection,
+    ) -> Result<()> {
+        match sqlx::query!(
+            "INSERT INTO staging_drives (id, name, page_token, corpus) VALUES ($1, $2, $3, $4)",
+            id,
+            name,
+            page_token,
+            corpus,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("创建暂存驱动器失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) async fn get_by_id(id: &str, pool: &Pool) -> Result<Option<Self>> {
+        match sqlx::query_as!(
+            Self,
+            r#"
+            SELECT
+                id, name, page_token, next_page_token,
+                complete as "complete: bool", corpus as "corpus: Corpus"
+            FROM staging_drives
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!("获取暂存驱动器失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) async fn get_all(pool: &Pool) -> Result<Vec<Self>> {
+        match sqlx::query_as!(
+            Self,
+            r#"
+            SELECT
+                id, name, page_token, next_page_token,
+                complete as "complete: bool", corpus as "corpus: Corpus"
+            FROM staging_drives
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+        {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                tracing::warn!("获取所有暂存驱动器失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// The number of staged folders and files, including those of the pages of earlier processes.
+    pub(crate) async fn count_items(id: &str, pool: &Pool) -> Result<i64> {
+        match sqlx::query!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM staging_folders WHERE drive_id = $1)
+                + (SELECT COUNT(*) FROM staging_files WHERE drive_id = $1) as "count!: i64"
+            "#,
+            id,
+        )
+        .fetch_one(pool)
+        .await
+        {
+            Ok(result) => Ok(result.count),
+            Err(e) => {
+                tracing::warn!("统计暂存项目失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Store the token of the next page, or mark the listing as complete after the last page.
+    pub(crate) async fn update_next_page_token(
+        id: &str,
+        next_page_token: Option<&str>,
+        conn: &mut Connection,
+    ) -> Result<()> {
+        match sqlx::query!(
+            "
+            UPDATE staging_drives
+            SET next_page_token = $2, complete = $2 IS NULL
+            WHERE id = $1
+            ",
+            id,
+            next_page_token,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("更新暂存页面令牌失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Stage a folder, replacing it when it was listed on an earlier page.
+    pub(crate) async fn add_folder(folder: &Folder, conn: &mut Connection) -> Result<()> {
+        match sqlx::query!(
+            "
+            INSERT OR REPLACE INTO staging_folders
+                (id, drive_id, name, trashed)
+            VALUES
+                ($1, $2, $3, $4)
+            ",
+            folder.id,
+            folder.drive_id,
+            folder.name,
+            folder.trashed,
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            Ok(_) => trace!(id = %folder.id, "staged folder"),
+            Err(e) => {
+                tracing::warn!("暂存文件夹失败: {}", e);
+                return Err(e);
+            }
+        }
+
+        Self::add_parents(&folder.id, &folder.drive_id, folder.parents(), conn).await
+    }
+
+    /// Stage a file, replacing it when it was listed on an earlier page.
+    pub(crate) async fn add_file(file: &File, conn: &mut Connection) -> Result<()> {
+        match sqlx::query!(
+            "
+            INSERT OR REPLACE INTO staging_files
+                (id, drive_id, name, trashed, kind, mime_type, md5, size, target_id, target_mime_type)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ",
+            file.id,
+            file.drive_id,
+            file.name,
+            file.trashed,
+            file.kind,
+            file.mime_type,
+            file.md5,
+            file.size,
+            file.target_id,
+            file.target_mime_type,
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            Ok(_) => trace!(id = %file.id, "staged file"),
+            Err(e) => {
+                tracing::warn!("暂存文件失败: {}", e);
+                return Err(e);
+            }
+        }
+
+        Self::add_parents(&file.id, &file.drive_id, file.parents(), conn).await
+    }
+
+    async fn add_parents(
+        id: &str,
+        drive_id: &str,
+        parents: &[String],
+        conn: &mut Connection,
+    ) -> Result<()> {
+        if let Err(e) = sqlx::query!(
+            "DELETE FROM staging_parents WHERE id = $1 AND drive_id = $2",
+            id,
+            drive_id,
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            tracing::warn!("删除暂存父文件夹失败: {}", e);
+            return Err(e);
+        }
+
+        for parent in parents {
+            if let Err(e) = sqlx::query!(
+                "INSERT INTO staging_parents (id, drive_id, parent) VALUES ($1, $2, $3)",
+                id,
+                drive_id,
+                parent,
+            )
+            .execute(&mut *conn)
+            .await
+            {
+                tracing::warn!("暂存父文件夹失败: {}", e);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the staged folders and files into the drive, which must already exist,
+    /// and remove the staged drive.
+    ///
+    /// The parents are moved last, so the triggers write them to the changelog.
+    pub(crate) async fn promote(id: &str, conn: &mut Connection) -> Result<()> {
+        if let Err(e) = sqlx::query!(
+            "
+            INSERT INTO folders (id, drive_id, name, trashed)
+            SELECT id, drive_id, name, trashed FROM staging_folders WHERE drive_id = $1
+            ",
+            id,
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            tracing::warn!("提升暂存文件夹失败: {}", e);
+            return Err(e);
+        }
+
+        if let Err(e) = sqlx::query!(
+            "
+            INSERT INTO files
+                (id, drive_id, name, trashed, kind, mime_type, md5, size, target_id, target_mime_type)
+            SELECT id, drive_id, name, trashed, kind, mime_type, md5, size, target_id, target_mime_type
+            FROM staging_files WHERE drive_id = $1
+            ",
+            id,
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            tracing::warn!("提升暂存文件失败: {}", e);
+            return Err(e);
+        }
+
+        if let Err(e) = sqlx::query!(
+            "
+            INSERT INTO parents (id, drive_id, parent)
+            SELECT id, drive_id, parent FROM staging_parents WHERE drive_id = $1
+            ",
+            id,
+        )
+        .execute(&mut *conn)
+        .await
+        {
+            tracing::warn!("提升暂存父文件夹失败: {}", e);
+            return Err(e);
+        }
+
+        Self::delete(id, conn).await?;
+        trace!(%id, "promoted staged drive");
+        Ok(())
+    }
+
+    /// Remove the staged drive, which cascades to its staged folders and files.
+    pub(crate) async fn delete(id: &str, conn: &mut Connection) -> Result<()> {
+        match sqlx::query!("DELETE FROM staging_drives WHERE id = $1", id)
+            .execute(conn)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("删除暂存驱动器失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::database::{Connection, Pool};
+use crate::ErrorKind;
+use chrono::{DateTime, Utc};
+use sqlx::Result;
+
+/// What a synchronisation of a drive has done.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum RunKind {
+    Full,
+    Partial,
+    /// The drive was no longer accessible and has been removed.
+    Removed,
+}
+
+/// The number of folders or files a synchronisation has changed.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ItemCounts {
+    pub created: i64,
+    pub updated: i64,
+    pub deleted: i64,
+}
+
+/// A single synchronisation of a drive, see [`Bernard::sync_history`](crate::Bernard::sync_history).
+#[derive(Debug)]
+pub struct SyncRun {
+    pub drive_id: String,
+    pub kind: RunKind,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// The page token a partial synchronisation started from.
+    pub old_page_token: Option<String>,
+    /// The page token the next synchronisation continues from, unless the drive has been removed.
+    pub new_page_token: Option<String>,
+    /// The number of changes fetched, or the number of items for a full synchronisation.
+    pub changes: i64,
+    pub folders: ItemCounts,
+    pub files: ItemCounts,
+    /// Why the synchronisation failed, or `None` when it succeeded.
+    pub error: Option<ErrorKind>,
+}
+
+struct SyncRunRow {
+    drive_id: String,
+    kind: RunKind,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    old_page_token: Option<String>,
+    new_page_token: Option<String>,
+    changes: i64,
+    folders_created: i64,
+    folders_updated: i64,
+    folders_deleted: i64,
+    files_created: i64,
+    files_updated: i64,
+    files_deleted: i64,
+    error: Option<ErrorKind>,
+}
+
+impl From<SyncRunRow> for SyncRun {
+    fn from(r: SyncRunRow) -> Self {
+        Self {
+            drive_id: r.drive_id,
+            kind: r.kind,
+            started_at: r.started_at,
+            finished_at: r.finished_at,
+            old_page_token: r.old_page_token,
+            new_page_token: r.new_page_token,
+            changes: r.changes,
+            folders: ItemCounts {
+                created: r.folders_created,
+                updated: r.folders_updated,
+                deleted: r.folders_deleted,
+            },
+            files: ItemCounts {
+                created: r.files_created,
+                updated: r.files_updated,
+                deleted: r.files_deleted,
+            },
+            error: r.error,
+        }
+    }
+}
+
+impl SyncRun {
+    pub(crate) fn start(drive_id: &str, kind: RunKind, old_page_token: Option<String>) -> Self {
+        let now = Utc::now();
+
+        Self {
+            drive_id: drive_id.to_owned(),
+            kind,
+            started_at: now,
+            finished_at: now,
+            old_page_token,
+            new_page_token: None,
+            changes: 0,
+            folders: ItemCounts::default(),
+            files: ItemCounts::default(),
+            error: None,
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Count the changed folders and files in the changelog of the synchronisation.
+    pub(crate) async fn count_changelog(
+        &mut self,
+        generation: i64,
+        conn: &mut Connection,
+    ) -> Result<()> {
+        // An existing folder or file has been updated when it has deleted rows, and created otherwise.
+        // Orphans are created and deleted within the same synchronisation, so they are not counted.
+        let query = |changelog: &str, table: &str| {
+            format!(
+                "
+                SELECT
+                    COALESCE(SUM(present AND NOT deleted), 0) as created,
+                    COALESCE(SUM(present AND deleted), 0) as updated,
+                    COALESCE(SUM(NOT present AND NOT created), 0) as deleted
+                FROM (
+                    SELECT
+                        MAX(NOT l.deleted) as created,
+                        MAX(l.deleted) as deleted,
+                        EXISTS (SELECT 1 FROM {} i WHERE i.id = l.id AND i.drive_id = l.drive_id) as present
+                    FROM {} l
+                    WHERE l.drive_id = $1 AND l.generation = $2
+                    GROUP BY l.id
+                )
+                ",
+                table, changelog
+            )
+        };
+
+        for (changelog, table, counts) in [
+            ("folder_changelog", "folders", &mut self.folders),
+            ("file_changelog", "files", &mut self.files),
+        ] {
+            match sqlx::query_as::<_, ItemCounts>(&query(changelog, table))
+                .bind(&self.drive_id)
+                .bind(generation)
+                .fetch_one(&mut *conn)
+                .await
+            {
+                Ok(result) => *counts = result,
+                Err(e) => {
+                    tracing::warn!("统计变更日志失败: {}", e);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn create(&self, conn: &mut Connection) -> Result<()> {
+        match sqlx::query!(
+            "
+            INSERT INTO sync_runs (
+                drive_id, kind, started_at, finished_at, old_page_token, new_page_token, changes,
+                folders_created, folders_updated, folders_deleted,
+                files_created, files_updated, files_deleted, error
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            ",
+            self.drive_id,
+            self.kind,
+            self.started_at,
+            self.finished_at,
+            self.old_page_token,
+            self.new_page_token,
+            self.changes,
+            self.folders.created,
+            self.folders.updated,
+            self.folders.deleted,
+            self.files.created,
+            self.files.updated,
+            self.files.deleted,
+            self.error,
+        )
+        .execute(conn)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::warn!("保存同步记录失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// All synchronisations of the drive, newest first.
+    pub(crate) async fn get_all(drive_id: &str, pool: &Pool) -> Result<Vec<Self>> {
+        match sqlx::query_as!(
+            SyncRunRow,
+            r#"
+            SELECT
+                drive_id, kind as "kind: RunKind",
+                started_at as "started_at: DateTime<Utc>", finished_at as "finished_at: DateTime<Utc>",
+                old_page_token, new_page_token, changes,
+                folders_created, folders_updated, folders_deleted,
+                files_created, files_updated, files_deleted,
+                error as "error: ErrorKind"
+            FROM sync_runs
+            WHERE drive_id = $1
+            ORDER BY id DESC
+            "#,
+            drive_id
+        )
+        .fetch_all(pool)
+        .await
+        {
+            Ok(rows) => Ok(rows.into_iter().map(|r| r.into()).collect()),
+            Err(e) => {
+                tracing::warn!("获取同步记录失败: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
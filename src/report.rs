@@ -0,0 +1,26 @@
+use crate::ItemCounts;
+use std::time::Duration;
+
+/// What a synchronisation has done, see [`SyncKind::report`](crate::SyncKind::report).
+#[derive(Debug, Default, Clone)]
+pub struct SyncReport {
+    /// The number of items listed by a full synchronisation, including the pages listed before
+    /// it was interrupted, or the number of changes fetched by a partial one.
+    pub listed: usize,
+    /// The IDs of the folders which are not reachable from the root folder, and have been skipped.
+    pub orphaned_folders: Vec<String>,
+    /// The IDs of the files whose parent folders are not part of the drive, and have been skipped.
+    pub orphaned_files: Vec<String>,
+    /// The generation of the changes, or `None` when nothing has changed.
+    /// See [`Bernard::changes_since`](crate::Bernard::changes_since).
+    pub generation: Option<i64>,
+    pub folders: ItemCounts,
+    pub files: ItemCounts,
+    /// The number of requests to the Google Drive API, including retries.
+    pub requests: usize,
+    pub retries: usize,
+    /// The time spent waiting for the Google Drive API.
+    pub fetch_duration: Duration,
+    /// The time spent writing to the database.
+    pub write_duration: Duration,
+}
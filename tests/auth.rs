@@ -0,0 +1,268 @@
+use bernard::{Account, AuthorizedUser, ErrorKind, RetryPolicy, StaticToken, SyncKind};
+use common::{Harness, DRIVE_ID};
+use fake_drive::FakeDrive;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn token_requests(harness: &Harness) -> usize {
+    harness
+        .fake
+        .requests()
+        .iter()
+        .filter(|request| request.as_str() == "/token")
+        .count()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn service_account_requests_a_token_once() {
+    let harness = Harness::start().await;
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert_eq!(token_requests(&harness), 1);
+    assert_eq!(harness.fake.token_subjects(), [None]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cached_token_is_reused_after_restart() {
+    let harness = Harness::start().await;
+
+    for _ in 0..2 {
+        let bernard = harness.builder().cache_tokens(true).build().await.unwrap();
+        bernard.sync_drive(DRIVE_ID).await.unwrap();
+        bernard.close().await;
+    }
+
+    assert_eq!(token_requests(&harness), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_cached_token_is_replaced() {
+    let harness = Harness::start().await;
+
+    let bernard = harness.builder().cache_tokens(true).build().await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    bernard.close().await;
+
+    harness.fake.revoke_issued_tokens();
+
+    // The rejected token is replaced by a new one, without failing the synchronisation.
+    let bernard = harness.builder().cache_tokens(true).build().await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    bernard.close().await;
+    assert_eq!(token_requests(&harness), 2);
+
+    // The next process reuses the new token.
+    let bernard = harness.builder().cache_tokens(true).build().await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert_eq!(token_requests(&harness), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cached_tokens_are_kept_per_token_url() {
+    let harness = Harness::start().await;
+    let other = FakeDrive::start().await;
+
+    let bernard = harness.builder().cache_tokens(true).build().await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    bernard.close().await;
+
+    let bernard = harness
+        .builder()
+        .cache_tokens(true)
+        .token_url(other.token_url())
+        .build()
+        .await
+        .unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert_eq!(token_requests(&harness), 1);
+    assert_eq!(other.requests(), ["/token"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tokens_are_not_cached_by_default() {
+    let harness = Harness::start().await;
+
+    for _ in 0..2 {
+        let bernard = harness.bernard().await;
+        bernard.sync_drive(DRIVE_ID).await.unwrap();
+        bernard.close().await;
+    }
+
+    assert_eq!(token_requests(&harness), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn service_account_impersonates_a_user() {
+    let harness = Harness::start().await;
+
+    let account = Account::from_json_str(fake_drive::SERVICE_ACCOUNT)
+        .unwrap()
+        .impersonate("user@example.com");
+
+    let bernard = harness.builder_with(account).build().await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert_eq!(
+        harness.fake.token_subjects(),
+        [Some("user@example.com".to_owned())]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn authorized_user_exchanges_the_refresh_token() {
+    let harness = Harness::start().await;
+
+    let user = AuthorizedUser::new("client-id", "client-secret", "refresh-token");
+    let bernard = harness.builder_with(user).build().await.unwrap();
+
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+    assert_eq!(token_requests(&harness), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn static_token_is_used_as_is() {
+    let harness = Harness::start().await;
+
+    let token = StaticToken::new(fake_drive::ACCESS_TOKEN);
+    let bernard = harness.builder_with(token).build().await.unwrap();
+
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+    assert_eq!(token_requests(&harness), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_static_token_is_rejected() {
+    let harness = Harness::start().await;
+
+    let bernard = harness
+        .builder_with(StaticToken::new("invalid"))
+        .build()
+        .await
+        .unwrap();
+
+    let error = bernard.sync_drive(DRIVE_ID).await.err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::Network);
+    assert!(error.to_string().contains("Invalid credentials"));
+}
+
+fn pooled(harness: &Harness) -> bernard::BernardBuilder {
+    harness
+        .builder_with(StaticToken::new("fake-access-token-a"))
+        .add_provider(StaticToken::new("fake-access-token-b"))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pooled_providers_take_turns() {
+    let harness = Harness::start().await;
+
+    let bernard = pooled(&harness).build().await.unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let tokens = harness.fake.access_tokens();
+    assert!(tokens.len() > 2);
+    assert!(tokens.windows(2).all(|pair| pair[0] != pair[1]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limited_provider_fails_over() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .rate_limit("/changes/startPageToken", Some(10), 1);
+
+    let bernard = pooled(&harness).build().await.unwrap();
+
+    let start = Instant::now();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    // The rate limited provider is avoided for the remaining requests.
+    let tokens = harness.fake.access_tokens();
+    assert!(tokens.iter().all(|token| token == "fake-access-token-b"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failover_retries_immediately() {
+    let harness = Harness::start().await;
+    harness.fake.rate_limit("/changes/startPageToken", None, 1);
+
+    let delays = Arc::new(Mutex::new(Vec::new()));
+    let hook_delays = delays.clone();
+
+    let bernard = pooled(&harness)
+        .retry_policy(RetryPolicy {
+            initial_interval: Duration::from_secs(5),
+            ..RetryPolicy::default()
+        })
+        .on_retry(move |retry| hook_delays.lock().unwrap().push(retry.delay))
+        .build()
+        .await
+        .unwrap();
+
+    let start = Instant::now();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(*delays.lock().unwrap(), [Duration::default()]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exceeded_quota_fails_over() {
+    let harness = Harness::start().await;
+    harness.fake.fail("/files", 403, "quotaExceeded", 1);
+
+    let bernard = pooled(&harness).build().await.unwrap();
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+
+    // Without another provider, the exceeded quota is not retried.
+    let harness = Harness::start().await;
+    harness.fake.fail("/files", 403, "quotaExceeded", 1);
+
+    let bernard = harness.bernard().await;
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+}
+
+#[test]
+fn account_from_json_str() {
+    let account = Account::from_json_str(fake_drive::SERVICE_ACCOUNT).unwrap();
+    assert_eq!(
+        account.client_email(),
+        "bernard@fake-drive.iam.gserviceaccount.com"
+    );
+
+    let error = Account::from_json_str("{}").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidJWK);
+}
+
+#[test]
+fn account_from_reader() {
+    let account = Account::from_reader(fake_drive::SERVICE_ACCOUNT.as_bytes()).unwrap();
+    assert_eq!(
+        account.client_email(),
+        "bernard@fake-drive.iam.gserviceaccount.com"
+    );
+}
+
+#[test]
+fn account_from_env() {
+    std::env::set_var("BERNARD_TEST_ACCOUNT", fake_drive::SERVICE_ACCOUNT);
+    assert!(Account::from_env("BERNARD_TEST_ACCOUNT").is_ok());
+
+    let error = Account::from_env("BERNARD_TEST_MISSING_ACCOUNT").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::WhereIsJWK);
+}
+
+#[test]
+fn account_from_missing_file() {
+    let error = Account::from_file("/nonexistent/account.json").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::WhereIsJWK);
+}
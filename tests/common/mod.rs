@@ -0,0 +1,122 @@
+// Not every test binary uses every helper.
+#![allow(dead_code)]
+
+use bernard::{Account, Bernard, BernardBuilder, ChangedPath, InnerPath, Path, TokenProvider};
+use fake_drive::FakeDrive;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const DRIVE_ID: &str = "drive";
+
+/// A temporary directory which is removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!(
+            "bernard-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+pub struct Harness {
+    pub fake: FakeDrive,
+    pub dir: TempDir,
+}
+
+impl Harness {
+    /// Start a fake Google Drive with a single empty Shared Drive.
+    pub async fn start() -> Self {
+        let fake = FakeDrive::start().await;
+        fake.add_drive(DRIVE_ID, "Drive");
+
+        Self {
+            fake,
+            dir: TempDir::new(),
+        }
+    }
+
+    /// A builder for a Bernard pointed at the fake, which shares the database between builds.
+    pub fn builder(&self) -> BernardBuilder {
+        let key = self.dir.0.join("account.json");
+        std::fs::write(&key, fake_drive::SERVICE_ACCOUNT).unwrap();
+
+        self.builder_with(Account::from_file(&key).unwrap())
+    }
+
+    /// A builder like [`Harness::builder`], authenticated by the provided token provider.
+    pub fn builder_with<P: TokenProvider + 'static>(&self, provider: P) -> BernardBuilder {
+        Bernard::builder(self.database_path().to_str().unwrap(), provider)
+            .api_base_url(self.fake.api_base_url())
+            .token_url(self.fake.token_url())
+    }
+
+    /// The SQLite database shared between builds.
+    pub fn database_path(&self) -> PathBuf {
+        self.dir.0.join("bernard.db")
+    }
+
+    pub async fn bernard(&self) -> Bernard {
+        self.builder().build().await.unwrap()
+    }
+}
+
+pub fn inner(path: &Path) -> &InnerPath {
+    match path {
+        Path::File(inner) | Path::Folder(inner) => inner,
+        Path::Document(inner) | Path::Shortcut(inner) => inner,
+    }
+}
+
+fn describe(path: &Path) -> String {
+    let kind = match path {
+        Path::File(_) => "file",
+        Path::Folder(_) => "folder",
+        Path::Document(_) => "document",
+        Path::Shortcut(_) => "shortcut",
+    };
+
+    format!("{} {}", kind, inner(path).path.display())
+}
+
+/// The paths as sorted `<kind> <path>` strings.
+pub fn describe_paths(paths: &[Path]) -> Vec<String> {
+    let mut paths: Vec<String> = paths.iter().map(describe).collect();
+    paths.sort();
+    paths
+}
+
+/// The changed paths as sorted `<+|-> <kind> <path>` strings,
+/// or `<moved|renamed> <kind> <from> -> <to>` strings.
+pub fn describe_changes(changes: &[ChangedPath]) -> Vec<String> {
+    let mut changes: Vec<String> = changes
+        .iter()
+        .map(|change| match change {
+            ChangedPath::Created(path) => format!("+ {}", describe(path)),
+            ChangedPath::Deleted(path) => format!("- {}", describe(path)),
+            ChangedPath::Moved { from, to } => {
+                format!("moved {} -> {}", describe(from), inner(to).path.display())
+            }
+            ChangedPath::Renamed { from, to } => {
+                format!("renamed {} -> {}", describe(from), inner(to).path.display())
+            }
+        })
+        .collect();
+
+    changes.sort();
+    changes
+}
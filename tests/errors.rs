@@ -0,0 +1,172 @@
+use bernard::{ErrorKind, RetryPolicy, SyncKind};
+use common::{describe_paths, Harness, DRIVE_ID};
+use fake_drive::Item;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+mod common;
+
+fn count_requests(harness: &Harness, pattern: &str) -> usize {
+    harness
+        .fake
+        .requests()
+        .iter()
+        .filter(|request| request.contains(pattern))
+        .count()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limited_requests_are_retried() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .upsert(DRIVE_ID, Item::file("file", "file.txt", DRIVE_ID));
+    harness.fake.rate_limit("/files", None, 1);
+
+    let bernard = harness.bernard().await;
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert!(matches!(kind, SyncKind::Full(_)));
+    assert_eq!(count_requests(&harness, "/files"), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retry_after_is_honoured() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .rate_limit("/changes/startPageToken", Some(1), 1);
+
+    let bernard = harness.bernard().await;
+
+    let start = Instant::now();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retry_after_date_is_honoured() {
+    let harness = Harness::start().await;
+    let until = SystemTime::now() + Duration::from_secs(2);
+    harness
+        .fake
+        .rate_limit_until("/changes/startPageToken", until, 1);
+
+    let bernard = harness.bernard().await;
+
+    // HTTP dates only have a precision of seconds.
+    let start = Instant::now();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn permission_denied_is_not_retried() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .fail("/files", 403, "insufficientFilePermissions", 1);
+
+    let bernard = harness.bernard().await;
+    let error = bernard.sync_drive(DRIVE_ID).await.err().unwrap();
+
+    assert_eq!(error.kind(), ErrorKind::Network);
+    assert_eq!(count_requests(&harness, "/files"), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_stop_after_max_attempts() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .fail("/changes/startPageToken", 503, "backendError", 5);
+
+    let retries = Arc::new(AtomicUsize::new(0));
+    let hook_retries = retries.clone();
+
+    let bernard = harness
+        .builder()
+        .retry_policy(RetryPolicy {
+            initial_interval: Duration::from_millis(10),
+            max_attempts: Some(3),
+            ..RetryPolicy::default()
+        })
+        .on_retry(move |retry| {
+            assert_eq!(retry.error.kind(), ErrorKind::Network);
+            hook_retries.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .await
+        .unwrap();
+
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+    assert_eq!(count_requests(&harness, "/changes/startPageToken"), 3);
+    assert_eq!(retries.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retry_hook_reports_the_retry_after_delay() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .rate_limit("/changes/startPageToken", Some(1), 1);
+
+    let delays = Arc::new(Mutex::new(Vec::new()));
+    let hook_delays = delays.clone();
+
+    let bernard = harness
+        .builder()
+        .retry_policy(RetryPolicy {
+            initial_interval: Duration::from_millis(10),
+            ..RetryPolicy::default()
+        })
+        .on_retry(move |retry| hook_delays.lock().unwrap().push(retry.delay))
+        .build()
+        .await
+        .unwrap();
+
+    let start = Instant::now();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    let elapsed = start.elapsed();
+
+    // The reported delay is the actual wait, which is not added to the backoff interval.
+    let delays = delays.lock().unwrap();
+    assert_eq!(delays.len(), 1);
+    assert!(delays[0] >= Duration::from_secs(1));
+    assert!(elapsed >= delays[0]);
+    assert!(elapsed < delays[0] + Duration::from_millis(500));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_change_list_is_rejected() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", DRIVE_ID));
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    // The file is moved into a folder which is missing from the change list.
+    fake.upsert_unreported(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", "folder"));
+
+    let error = bernard.sync_drive(DRIVE_ID).await.err().unwrap();
+    assert!(error.is_partial_change_list());
+    assert_eq!(error.kind(), ErrorKind::PartialChangeList);
+
+    // Nothing has been deleted and the changes are fetched again.
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(describe_paths(&paths), ["file /file.txt"]);
+
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        ["file /Folder/file.txt", "folder /Folder"]
+    );
+}
@@ -0,0 +1,193 @@
+use bernard::{Bernard, ChangeSet, ChangedFile, SyncKind};
+use common::{describe_changes, Harness, DRIVE_ID};
+use fake_drive::Item;
+
+mod common;
+
+/// Fully synchronise a drive with a single file, then rename it and add another file.
+/// The consumers are registered beforehand, so they retain every change.
+async fn build_history(harness: &Harness, consumers: &[&str]) -> Bernard {
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", DRIVE_ID));
+
+    let bernard = harness.bernard().await;
+    for consumer in consumers {
+        bernard.register_consumer(consumer, DRIVE_ID).await.unwrap();
+    }
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    fake.upsert(DRIVE_ID, Item::file("file", "renamed.txt", DRIVE_ID));
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    fake.upsert(DRIVE_ID, Item::file("new", "new.txt", DRIVE_ID));
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    bernard
+}
+
+fn describe_sets(sets: &[ChangeSet]) -> Vec<Vec<String>> {
+    sets.iter()
+        .map(|set| describe_changes(&set.paths))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn changes_are_retained_across_syncs() {
+    let harness = Harness::start().await;
+    let bernard = build_history(&harness, &["consumer"]).await;
+
+    // Nothing has changed, so no generation is added.
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let sets = bernard.changes_since(DRIVE_ID, 0).await.unwrap();
+    assert_eq!(
+        describe_sets(&sets),
+        [
+            vec!["+ file /file.txt"],
+            vec!["renamed file /file.txt -> /renamed.txt"],
+            vec!["+ file /new.txt"],
+        ]
+    );
+    assert!(sets.windows(2).all(|w| w[0].generation < w[1].generation));
+
+    // The changed files are retained along with the paths.
+    let renamed: Vec<(bool, &str)> = sets[1]
+        .files
+        .iter()
+        .map(|file| match file {
+            ChangedFile::Created(file) => (true, file.name.as_str()),
+            ChangedFile::Deleted(file) => (false, file.name.as_str()),
+        })
+        .collect();
+    assert_eq!(renamed.len(), 2);
+    assert!(renamed.contains(&(false, "file.txt")));
+    assert!(renamed.contains(&(true, "renamed.txt")));
+    assert!(sets[1].folders.is_empty());
+
+    let later = bernard
+        .changes_since(DRIVE_ID, sets[1].generation)
+        .await
+        .unwrap();
+    assert_eq!(describe_sets(&later), [vec!["+ file /new.txt"]]);
+
+    // The history outlives the drive.
+    harness.fake.remove_drive(DRIVE_ID);
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let removed = bernard
+        .changes_since(DRIVE_ID, sets[2].generation)
+        .await
+        .unwrap();
+    assert_eq!(
+        describe_sets(&removed),
+        [vec!["- file /new.txt", "- file /renamed.txt"]]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pruning_waits_for_every_consumer() {
+    let harness = Harness::start().await;
+    let bernard = build_history(&harness, &["fast", "slow"]).await;
+
+    let sets = bernard.changes_since(DRIVE_ID, 0).await.unwrap();
+    let generations: Vec<i64> = sets.iter().map(|set| set.generation).collect();
+
+    bernard
+        .acknowledge("fast", DRIVE_ID, generations[2])
+        .await
+        .unwrap();
+    bernard
+        .acknowledge("slow", DRIVE_ID, generations[0])
+        .await
+        .unwrap();
+
+    // Cursors never move backwards.
+    bernard
+        .acknowledge("fast", DRIVE_ID, generations[1])
+        .await
+        .unwrap();
+    assert_eq!(
+        bernard.cursor("fast", DRIVE_ID).await.unwrap(),
+        generations[2]
+    );
+    assert_eq!(bernard.cursor("unknown", DRIVE_ID).await.unwrap(), 0);
+
+    assert_eq!(bernard.prune_changes(DRIVE_ID).await.unwrap(), 1);
+
+    let cursor = bernard.cursor("slow", DRIVE_ID).await.unwrap();
+    let remaining = bernard.changes_since(DRIVE_ID, cursor).await.unwrap();
+    assert_eq!(remaining.len(), 2);
+
+    bernard.remove_consumer("slow").await.unwrap();
+    assert_eq!(bernard.prune_changes(DRIVE_ID).await.unwrap(), 2);
+    assert!(bernard.changes_since(DRIVE_ID, 0).await.unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registered_consumers_hold_back_pruning() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", DRIVE_ID));
+
+    let bernard = harness.bernard().await;
+    bernard
+        .register_consumer("consumer", DRIVE_ID)
+        .await
+        .unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    // The consumer has not acknowledged anything yet.
+    assert_eq!(bernard.prune_changes(DRIVE_ID).await.unwrap(), 0);
+
+    let sets = bernard.changes_since(DRIVE_ID, 0).await.unwrap();
+    assert_eq!(describe_sets(&sets), [vec!["+ file /file.txt"]]);
+
+    // Registering again keeps the cursor.
+    bernard
+        .acknowledge("consumer", DRIVE_ID, sets[0].generation)
+        .await
+        .unwrap();
+    bernard
+        .register_consumer("consumer", DRIVE_ID)
+        .await
+        .unwrap();
+    assert_eq!(
+        bernard.cursor("consumer", DRIVE_ID).await.unwrap(),
+        sets[0].generation
+    );
+
+    assert_eq!(bernard.prune_changes(DRIVE_ID).await.unwrap(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn history_without_consumers_is_pruned_by_every_sync() {
+    let harness = Harness::start().await;
+    let bernard = build_history(&harness, &[]).await;
+
+    let sets = bernard.changes_since(DRIVE_ID, 0).await.unwrap();
+    assert_eq!(describe_sets(&sets), [vec!["+ file /new.txt"]]);
+    assert_eq!(sets[0].files.len(), 1);
+
+    // The changes of the last synchronisation remain available until the next one.
+    harness
+        .fake
+        .upsert(DRIVE_ID, Item::file("other", "other.txt", DRIVE_ID));
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes,
+        _ => panic!("expected a partial sync"),
+    };
+
+    let sets = bernard.changes_since(DRIVE_ID, 0).await.unwrap();
+    assert_eq!(describe_sets(&sets), [vec!["+ file /other.txt"]]);
+    assert_eq!(changes.report().generation, Some(sets[0].generation));
+    assert_eq!(changes.files().await.unwrap().len(), 1);
+
+    // Nothing has changed, so the last changes are kept.
+    let unchanged = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes,
+        _ => panic!("expected a partial sync"),
+    };
+    assert_eq!(unchanged.report().generation, None);
+    assert!(unchanged.paths().await.unwrap().is_empty());
+    assert_eq!(bernard.changes_since(DRIVE_ID, 0).await.unwrap().len(), 1);
+}
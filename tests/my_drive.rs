@@ -0,0 +1,163 @@
+use bernard::SyncKind;
+use common::{describe_changes, describe_paths, Harness};
+use fake_drive::{FakeDrive, Item};
+
+mod common;
+
+const ROOT_ID: &str = "root";
+
+/// A folder with a file, and two items shared with the user from outside of My Drive.
+fn populate(harness: &Harness) {
+    let fake = &harness.fake;
+    fake.add_my_drive(ROOT_ID);
+
+    fake.upsert(ROOT_ID, Item::folder("folder", "Folder", ROOT_ID));
+    fake.upsert(ROOT_ID, Item::file("file", "file.txt", "folder"));
+    fake.upsert(
+        ROOT_ID,
+        Item::file("shared", "shared.txt", ROOT_ID).with_parents(&[]),
+    );
+    fake.upsert(ROOT_ID, Item::file("foreign", "foreign.txt", "elsewhere"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_sync_of_my_drive() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    assert_eq!(bernard.my_drive_id().await.unwrap(), ROOT_ID);
+
+    let kind = bernard.sync_my_drive().await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+
+    // The lookup within the synchronisation is counted, unlike the one above.
+    let api_requests = harness
+        .fake
+        .requests()
+        .iter()
+        .filter(|request| !request.starts_with("/token"))
+        .count();
+    assert_eq!(kind.report().requests, api_requests - 1);
+
+    let paths = bernard.paths(ROOT_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        [
+            "file /Folder/file.txt",
+            "file /Shared with me/foreign.txt",
+            "file /Shared with me/shared.txt",
+            "folder /Folder",
+            "folder /Shared with me",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_sync_of_my_drive() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_my_drive().await.unwrap();
+
+    let fake = &harness.fake;
+    fake.upsert(
+        ROOT_ID,
+        Item::file("new", "new.txt", ROOT_ID).with_parents(&[]),
+    );
+    fake.upsert(ROOT_ID, Item::file("moved", "moved.txt", "elsewhere"));
+    fake.remove(ROOT_ID, "folder");
+
+    let changes = match bernard.sync_my_drive().await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    // The descendants of a removed folder are removed rather than shared.
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "+ file /Shared with me/moved.txt",
+            "+ file /Shared with me/new.txt",
+            "- file /Folder/file.txt",
+            "- folder /Folder",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_all_leaves_my_drive_alone() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_my_drive().await.unwrap();
+
+    let results = bernard.sync_all().await.unwrap();
+    assert_eq!(results.keys().collect::<Vec<_>>(), [common::DRIVE_ID]);
+    assert_eq!(bernard.paths(ROOT_ID).await.unwrap().len(), 5);
+
+    // My Drive is synchronised partially by its drive ID as well.
+    let kind = bernard.sync_drive(ROOT_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Partial(_)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn my_drive_is_never_removed() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_my_drive().await.unwrap();
+
+    harness.fake.fail("/changes?", 404, "notFound", 1);
+    assert!(bernard.sync_my_drive().await.is_err());
+    assert_eq!(bernard.paths(ROOT_ID).await.unwrap().len(), 5);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shared_items_of_different_users_are_kept_apart() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let other = FakeDrive::start().await;
+    other.add_my_drive("other-root");
+    other.upsert(
+        "other-root",
+        Item::file("other-shared", "other.txt", "other-root").with_parents(&[]),
+    );
+
+    let bernard = harness.bernard().await;
+    bernard.sync_my_drive().await.unwrap();
+
+    // Another user synchronised into the same database.
+    let other_bernard = harness
+        .builder()
+        .api_base_url(other.api_base_url())
+        .token_url(other.token_url())
+        .build()
+        .await
+        .unwrap();
+    other_bernard.sync_my_drive().await.unwrap();
+
+    let children = bernard
+        .children(ROOT_ID, "root:shared-with-me")
+        .await
+        .unwrap();
+    let ids: Vec<&str> = children.iter().map(|entry| entry.id()).collect();
+    assert_eq!(ids, ["foreign", "shared"]);
+
+    let children = bernard
+        .children("other-root", "other-root:shared-with-me")
+        .await
+        .unwrap();
+    let ids: Vec<&str> = children.iter().map(|entry| entry.id()).collect();
+    assert_eq!(ids, ["other-shared"]);
+
+    let stat = bernard
+        .stat("other-root", "other-root:shared-with-me")
+        .await
+        .unwrap();
+    assert_eq!(stat.unwrap().drive_id, "other-root");
+}
@@ -0,0 +1,161 @@
+use bernard::{Bernard, Entry, FileKind};
+use common::{describe_paths, Harness, DRIVE_ID};
+use fake_drive::Item;
+use futures::TryStreamExt;
+
+mod common;
+
+/// A folder with a file, a trashed folder with a trashed file, and a file in two folders.
+async fn synchronise(harness: &Harness) -> Bernard {
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", "folder"));
+    fake.upsert(
+        DRIVE_ID,
+        Item::folder("trash", "Trash", DRIVE_ID).with_trashed(true),
+    );
+    fake.upsert(
+        DRIVE_ID,
+        Item::file("trashed", "trashed.txt", "trash").with_trashed(true),
+    );
+    fake.upsert(
+        DRIVE_ID,
+        Item::file("shared", "shared.txt", DRIVE_ID).with_parents(&[DRIVE_ID, "folder"]),
+    );
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+    bernard
+}
+
+fn ids(entries: &[Entry]) -> Vec<&str> {
+    entries.iter().map(|entry| entry.id()).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lookup_follows_the_path() {
+    let harness = Harness::start().await;
+    let bernard = synchronise(&harness).await;
+
+    let root = bernard.lookup(DRIVE_ID, "/").await.unwrap();
+    assert_eq!(ids(&root), [DRIVE_ID]);
+    assert_eq!(root[0].name(), "Drive");
+
+    let file = bernard.lookup(DRIVE_ID, "/Folder/file.txt").await.unwrap();
+    assert_eq!(ids(&file), ["file"]);
+
+    let shared = bernard.lookup(DRIVE_ID, "/shared.txt").await.unwrap();
+    assert_eq!(ids(&shared), ["shared"]);
+
+    let missing = bernard.lookup(DRIVE_ID, "/Missing/file.txt").await.unwrap();
+    assert!(missing.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn children_of_a_folder() {
+    let harness = Harness::start().await;
+    let bernard = synchronise(&harness).await;
+
+    let children = bernard.children(DRIVE_ID, "folder").await.unwrap();
+    assert_eq!(ids(&children), ["file", "shared"]);
+
+    let root = bernard.children(DRIVE_ID, DRIVE_ID).await.unwrap();
+    assert_eq!(ids(&root), ["folder", "trash", "shared"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn trashed_is_the_same_for_every_read() {
+    let harness = Harness::start().await;
+    let bernard = synchronise(&harness).await;
+
+    for id in &["trash", "trashed"] {
+        assert!(bernard.stat(DRIVE_ID, id).await.unwrap().unwrap().trashed);
+    }
+    assert!(
+        !bernard
+            .stat(DRIVE_ID, "folder")
+            .await
+            .unwrap()
+            .unwrap()
+            .trashed
+    );
+
+    let trashed = |entry: &Entry| match entry {
+        Entry::Folder(folder) => folder.trashed,
+        Entry::File(file) => file.trashed,
+    };
+
+    let root = bernard.children(DRIVE_ID, DRIVE_ID).await.unwrap();
+    let root: Vec<bool> = root.iter().map(trashed).collect();
+    assert_eq!(root, [false, true, false]);
+
+    let trash = bernard.children(DRIVE_ID, "trash").await.unwrap();
+    assert!(trash.iter().all(trashed));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stat_of_folders_and_files() {
+    let harness = Harness::start().await;
+    let bernard = synchronise(&harness).await;
+
+    let file = bernard.stat(DRIVE_ID, "file").await.unwrap().unwrap();
+    assert_eq!(file.drive_id, DRIVE_ID);
+    assert_eq!(file.name, "file.txt");
+    assert_eq!(file.kind, Some(FileKind::Binary));
+    assert_eq!(file.md5.as_deref(), Some("md5-file"));
+    assert_eq!(file.size, Some(4));
+
+    let folder = bernard.stat(DRIVE_ID, "folder").await.unwrap().unwrap();
+    assert_eq!(folder.kind, None);
+    assert_eq!(folder.md5, None);
+    assert_eq!(folder.size, None);
+
+    assert!(bernard.stat(DRIVE_ID, "missing").await.unwrap().is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn walk_and_path_of() {
+    let harness = Harness::start().await;
+    let bernard = synchronise(&harness).await;
+
+    let walked: Vec<_> = bernard.walk(DRIVE_ID).try_collect().await.unwrap();
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(describe_paths(&walked), describe_paths(&paths));
+    assert_eq!(walked.len(), 6);
+
+    let shared = bernard.path_of(DRIVE_ID, "shared").await.unwrap();
+    assert_eq!(
+        describe_paths(&shared),
+        ["file /Folder/shared.txt", "file /shared.txt"]
+    );
+
+    assert!(bernard
+        .path_of(DRIVE_ID, "missing")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_are_scoped_to_the_drive() {
+    let harness = Harness::start().await;
+    let bernard = synchronise(&harness).await;
+
+    // The same item ID within another drive.
+    let fake = &harness.fake;
+    fake.add_my_drive("root");
+    fake.upsert("root", Item::folder("folder", "Mine", "root"));
+    fake.upsert("root", Item::file("file", "mine.txt", "folder"));
+    bernard.sync_my_drive().await.unwrap();
+
+    let file = bernard.stat(DRIVE_ID, "file").await.unwrap().unwrap();
+    assert_eq!(file.name, "file.txt");
+    let file = bernard.stat("root", "file").await.unwrap().unwrap();
+    assert_eq!(file.name, "mine.txt");
+
+    let children = bernard.children("root", "folder").await.unwrap();
+    assert_eq!(ids(&children), ["file"]);
+
+    let paths = bernard.path_of("root", "file").await.unwrap();
+    assert_eq!(describe_paths(&paths), ["file /Mine/mine.txt"]);
+}
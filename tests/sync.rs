@@ -0,0 +1,638 @@
+use bernard::{ChangedFile, ChangedFolder, ChangedPath, ShortcutPolicy, SyncKind};
+use common::{describe_changes, describe_paths, inner, Harness, DRIVE_ID};
+use fake_drive::{Item, FOLDER_MIME_TYPE};
+use std::time::{Duration, Instant};
+
+mod common;
+
+/// A folder with a file, a document and a file which is also in the root folder.
+fn populate(harness: &Harness) {
+    let fake = &harness.fake;
+
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", "folder"));
+    fake.upsert(DRIVE_ID, Item::document("document", "Document", "folder"));
+    fake.upsert(
+        DRIVE_ID,
+        Item::file("shared", "shared.txt", "folder").with_parents(&[DRIVE_ID, "folder"]),
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_sync_stores_the_tree() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        [
+            "document /Folder/Document",
+            "file /Folder/file.txt",
+            "file /Folder/shared.txt",
+            "file /shared.txt",
+            "folder /Folder",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_sync_reports_changed_paths() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    harness
+        .fake
+        .upsert(DRIVE_ID, Item::file("file", "renamed.txt", "folder"));
+    harness
+        .fake
+        .upsert(DRIVE_ID, Item::file("new", "new.txt", DRIVE_ID));
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "+ file /new.txt",
+            "renamed file /Folder/file.txt -> /Folder/renamed.txt",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_sync_reports_changed_folders_and_files() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::file("file", "renamed.txt", "folder"));
+    fake.upsert(DRIVE_ID, Item::folder("new", "New", DRIVE_ID));
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes,
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    // The changelog keeps the previous state as deleted and the new state as created.
+    let mut files: Vec<String> = changes
+        .files()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|file| match file {
+            ChangedFile::Created(file) => format!("+ {}", file.name),
+            ChangedFile::Deleted(file) => format!("- {}", file.name),
+        })
+        .collect();
+    files.sort();
+    assert_eq!(files, ["+ renamed.txt", "- file.txt"]);
+
+    let folders: Vec<String> = changes
+        .folders()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|folder| match folder {
+            ChangedFolder::Created(folder) => format!("+ {}", folder.name),
+            ChangedFolder::Deleted(folder) => format!("- {}", folder.name),
+        })
+        .collect();
+    assert_eq!(folders, ["+ New"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_sync_pairs_moves() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::file("file", "moved.txt", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Renamed", DRIVE_ID));
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    // The descendants of a renamed folder are not reported separately.
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "moved file /Folder/file.txt -> /moved.txt",
+            "renamed folder /Folder -> /Renamed",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn move_out_of_a_linked_folder_keeps_the_former_path() {
+    let harness = Harness::start().await;
+    populate(&harness);
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("other", "Other", DRIVE_ID));
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    // The folder only gains a parent, so it has no deleted row in the changelog.
+    fake.upsert(
+        DRIVE_ID,
+        Item::folder("folder", "Folder", DRIVE_ID).with_parents(&[DRIVE_ID, "other"]),
+    );
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", DRIVE_ID));
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "+ folder /Other/Folder",
+            "moved file /Folder/file.txt -> /file.txt",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn descendants_of_moved_folders_are_expanded() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("sub", "Sub", "folder"));
+    fake.upsert(DRIVE_ID, Item::file("deep", "deep.txt", "sub"));
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Renamed", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::folder("sub", "Moved", DRIVE_ID));
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths_with_descendants().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    // The shared file keeps its path in the root folder.
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "moved document /Folder/Document -> /Renamed/Document",
+            "moved file /Folder/Sub/deep.txt -> /Moved/deep.txt",
+            "moved file /Folder/file.txt -> /Renamed/file.txt",
+            "moved file /Folder/shared.txt -> /Renamed/shared.txt",
+            "moved folder /Folder/Sub -> /Moved",
+            "renamed folder /Folder -> /Renamed",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn descendants_of_trashed_folders_are_expanded() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    harness.fake.upsert(
+        DRIVE_ID,
+        Item::folder("folder", "Folder", DRIVE_ID).with_trashed(true),
+    );
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths_with_descendants().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    assert!(changes.iter().all(|change| match change {
+        ChangedPath::Created(path) => path.trashed(),
+        _ => false,
+    }));
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "+ document /Folder/Document",
+            "+ file /Folder/file.txt",
+            "+ file /Folder/shared.txt",
+            "+ folder /Folder",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removed_folder_deletes_descendants() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    harness.fake.remove(DRIVE_ID, "folder");
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Partial(changes) => changes.paths().await.unwrap(),
+        _ => panic!("expected a partial synchronisation"),
+    };
+
+    assert_eq!(
+        describe_changes(&changes),
+        [
+            "- document /Folder/Document",
+            "- file /Folder/file.txt",
+            "- file /Folder/shared.txt",
+            "- folder /Folder",
+        ]
+    );
+
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(describe_paths(&paths), ["file /shared.txt"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removed_drive_is_purged() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    harness.fake.remove_drive(DRIVE_ID);
+
+    let changes = match bernard.sync_drive(DRIVE_ID).await.unwrap() {
+        SyncKind::Removed(changes) => changes.paths().await.unwrap(),
+        _ => panic!("expected the drive to be removed"),
+    };
+
+    assert_eq!(changes.len(), 5);
+    assert!(bernard.paths(DRIVE_ID).await.unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn drive_is_kept_when_only_the_change_list_is_not_found() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    // Such as an invalid page token, while the drive itself is still accessible.
+    harness.fake.fail("/changes?", 404, "notFound", 1);
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+    assert_eq!(bernard.paths(DRIVE_ID).await.unwrap().len(), 5);
+
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Partial(_)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interrupted_full_sync_resumes() {
+    let harness = Harness::start().await;
+    populate(&harness);
+    harness.fake.set_max_page_size(2);
+
+    // The second page fails with a non-retryable error.
+    harness.fake.fail("&pageToken=2&", 400, "badRequest", 1);
+
+    let bernard = harness.bernard().await;
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+    assert!(bernard.paths(DRIVE_ID).await.unwrap().is_empty());
+
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+    assert_eq!(bernard.paths(DRIVE_ID).await.unwrap().len(), 5);
+
+    // The items of the first page are listed as well.
+    assert_eq!(kind.report().listed, 4);
+
+    // The first page is not requested again.
+    let first_pages = harness
+        .fake
+        .requests()
+        .into_iter()
+        .filter(|request| request.contains("/files?") && !request.contains("pageToken"))
+        .count();
+    assert_eq!(first_pages, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_resumed_page_token_restarts_the_listing() {
+    let harness = Harness::start().await;
+    populate(&harness);
+    harness.fake.set_max_page_size(2);
+
+    // The second page fails, and so does its token when the synchronisation resumes.
+    harness.fake.fail("&pageToken=2&", 400, "badRequest", 2);
+
+    let bernard = harness.bernard().await;
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+    assert_eq!(bernard.paths(DRIVE_ID).await.unwrap().len(), 5);
+    assert_eq!(kind.report().listed, 4);
+
+    // The listing has started over from the first page.
+    let first_pages = harness
+        .fake
+        .requests()
+        .into_iter()
+        .filter(|request| request.contains("/files?") && !request.contains("pageToken"))
+        .count();
+    assert_eq!(first_pages, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interrupted_full_sync_of_a_removed_drive_is_abandoned() {
+    let harness = Harness::start().await;
+    populate(&harness);
+    harness.fake.set_max_page_size(2);
+    harness.fake.fail("&pageToken=2&", 400, "badRequest", 1);
+
+    let bernard = harness.bernard().await;
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+
+    // The drive is deleted before the synchronisation resumes.
+    harness.fake.remove_drive(DRIVE_ID);
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+
+    // The staged pages are gone, so a drive with the same ID starts from the first page.
+    harness.fake.add_drive(DRIVE_ID, "Drive");
+    harness
+        .fake
+        .upsert(DRIVE_ID, Item::file("file", "file.txt", DRIVE_ID));
+
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    assert!(matches!(kind, SyncKind::Full(_)));
+
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(describe_paths(&paths), ["file /file.txt"]);
+
+    let first_pages = harness
+        .fake
+        .requests()
+        .into_iter()
+        .filter(|request| request.contains("/files?") && !request.contains("pageToken"))
+        .count();
+    assert_eq!(first_pages, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_all_abandons_full_syncs_of_drives_which_are_gone() {
+    let harness = Harness::start().await;
+    harness.fake.add_drive("other", "Other");
+    harness
+        .fake
+        .upsert("other", Item::file("first", "first.txt", "other"));
+    harness
+        .fake
+        .upsert("other", Item::file("second", "second.txt", "other"));
+    harness.fake.set_max_page_size(1);
+    harness.fake.fail("&pageToken=1&", 400, "badRequest", 1);
+
+    let bernard = harness.bernard().await;
+    assert!(bernard.sync_drive("other").await.is_err());
+
+    // The drive is not listed anymore, so its staged pages are removed.
+    harness.fake.remove_drive("other");
+    let results = bernard.sync_all().await.unwrap();
+    assert!(!results.contains_key("other"));
+
+    harness.fake.add_drive("other", "Other");
+    bernard.sync_drive("other").await.unwrap();
+
+    let first_pages = harness
+        .fake
+        .requests()
+        .into_iter()
+        .filter(|request| request.contains("/files?") && request.contains("driveId=other"))
+        .filter(|request| !request.contains("pageToken"))
+        .count();
+    assert_eq!(first_pages, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_all_discovers_drives() {
+    let harness = Harness::start().await;
+    populate(&harness);
+
+    harness.fake.add_drive("other", "Other");
+    harness
+        .fake
+        .upsert("other", Item::file("other-file", "other.txt", "other"));
+
+    let bernard = harness.bernard().await;
+    let results = bernard.sync_all().await.unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert!(results
+        .values()
+        .all(|result| matches!(result, Ok(SyncKind::Full(_)))));
+
+    let paths = bernard.paths("other").await.unwrap();
+    assert_eq!(describe_paths(&paths), ["file /other.txt"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shortcuts_are_links_or_followed() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", "folder"));
+    fake.upsert(
+        DRIVE_ID,
+        Item::shortcut("link", "Link", DRIVE_ID, "folder", FOLDER_MIME_TYPE),
+    );
+    fake.upsert(
+        DRIVE_ID,
+        Item::shortcut(
+            "dangling",
+            "Dangling",
+            DRIVE_ID,
+            "missing",
+            FOLDER_MIME_TYPE,
+        ),
+    );
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        [
+            "file /Folder/file.txt",
+            "folder /Folder",
+            "shortcut /Dangling",
+            "shortcut /Link",
+        ]
+    );
+
+    let bernard = harness
+        .builder()
+        .shortcut_policy(ShortcutPolicy::Follow)
+        .build()
+        .await
+        .unwrap();
+
+    // Shortcuts to a target which has not been synchronised remain links.
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        [
+            "file /Folder/file.txt",
+            "file /Link/file.txt",
+            "folder /Folder",
+            "folder /Link",
+            "shortcut /Dangling",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn followed_shortcuts_do_not_cycle() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(
+        DRIVE_ID,
+        Item::shortcut("back", "Back", "folder", "folder", FOLDER_MIME_TYPE),
+    );
+
+    let bernard = harness
+        .builder()
+        .shortcut_policy(ShortcutPolicy::Follow)
+        .build()
+        .await
+        .unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    // The shortcut within the resolved folder is not resolved again.
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        [
+            "folder /Folder",
+            "folder /Folder/Back",
+            "shortcut /Folder/Back/Back",
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn followed_shortcuts_cross_drives() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.add_drive("other", "Other");
+    fake.upsert("other", Item::folder("target", "Target", "other"));
+    fake.upsert("other", Item::file("other-file", "other.txt", "target"));
+    fake.upsert(
+        DRIVE_ID,
+        Item::shortcut("link", "Link", DRIVE_ID, "target", FOLDER_MIME_TYPE),
+    );
+
+    let bernard = harness
+        .builder()
+        .shortcut_policy(ShortcutPolicy::Follow)
+        .build()
+        .await
+        .unwrap();
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    // The target drive has not been synchronised yet.
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(describe_paths(&paths), ["shortcut /Link"]);
+
+    bernard.sync_drive("other").await.unwrap();
+
+    let paths = bernard.paths(DRIVE_ID).await.unwrap();
+    assert_eq!(
+        describe_paths(&paths),
+        ["file /Link/other.txt", "folder /Link"]
+    );
+    assert!(paths.iter().all(|path| inner(path).drive_id == DRIVE_ID));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_drives_is_bounded_and_isolates_failures() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+
+    let drive_ids = ["first", "second", "third", "fourth"];
+    for drive_id in &drive_ids {
+        fake.add_drive(drive_id, drive_id);
+        fake.upsert(
+            drive_id,
+            Item::file(&format!("{}-file", drive_id), "file.txt", drive_id),
+        );
+    }
+
+    fake.set_latency(Duration::from_millis(50));
+    fake.fail("driveId=second", 400, "badRequest", 1);
+
+    let bernard = harness.bernard().await;
+    let results = bernard.sync_drives(drive_ids.iter().copied(), 2).await;
+
+    assert_eq!(results.len(), 4);
+    assert!(results["second"].is_err());
+    for drive_id in &["first", "third", "fourth"] {
+        assert!(matches!(results[*drive_id], Ok(SyncKind::Full(_))));
+
+        let paths = bernard.paths(drive_id).await.unwrap();
+        assert_eq!(describe_paths(&paths), ["file /file.txt"]);
+    }
+
+    // The drives overlap, but never more than two at the same time.
+    assert_eq!(fake.max_concurrent_requests(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_sync_does_not_block_other_drives() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+
+    fake.add_drive("slow", "Slow");
+    for i in 0..10 {
+        let id = format!("slow-{}", i);
+        fake.upsert("slow", Item::file(&id, &format!("{}.txt", id), "slow"));
+    }
+
+    fake.add_drive("fast", "Fast");
+    fake.upsert("fast", Item::file("fast-file", "file.txt", "fast"));
+
+    fake.set_max_page_size(1);
+    fake.set_latency(Duration::from_millis(50));
+
+    let bernard = harness.bernard().await;
+
+    // The database is only locked while a page is written, not while the next page is fetched.
+    let timed = |drive_id: &'static str| {
+        let bernard = &bernard;
+        async move {
+            bernard.sync_drive(drive_id).await.unwrap();
+            Instant::now()
+        }
+    };
+
+    let (slow, fast) = tokio::join!(timed("slow"), timed("fast"));
+    assert!(fast < slow);
+    assert_eq!(bernard.paths("slow").await.unwrap().len(), 10);
+}
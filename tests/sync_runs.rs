@@ -0,0 +1,133 @@
+use bernard::{ErrorKind, ItemCounts, RunKind, SyncKind};
+use common::{Harness, DRIVE_ID};
+use fake_drive::Item;
+use std::time::Duration;
+
+mod common;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn every_sync_is_recorded() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", "folder"));
+
+    let bernard = harness.bernard().await;
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    fake.upsert(DRIVE_ID, Item::file("file", "renamed.txt", "folder"));
+    fake.upsert(DRIVE_ID, Item::file("new", "new.txt", DRIVE_ID));
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let runs = bernard.sync_history(DRIVE_ID).await.unwrap();
+    assert_eq!(runs.len(), 2);
+    let (partial, full) = (&runs[0], &runs[1]);
+
+    assert_eq!(full.kind, RunKind::Full);
+    assert!(full.succeeded());
+    assert_eq!(full.old_page_token, None);
+    assert_eq!(full.changes, 2);
+    assert_eq!(full.folders.created, 1);
+    assert_eq!(full.files.created, 1);
+
+    assert_eq!(partial.kind, RunKind::Partial);
+    assert_eq!(partial.old_page_token, full.new_page_token);
+    assert_ne!(partial.new_page_token, partial.old_page_token);
+    assert_eq!(partial.changes, 2);
+    assert_eq!(partial.folders, ItemCounts::default());
+    assert_eq!(
+        partial.files,
+        ItemCounts {
+            created: 1,
+            updated: 1,
+            deleted: 0,
+        }
+    );
+    assert!(partial.started_at >= full.finished_at);
+    assert!(partial.finished_at >= partial.started_at);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_sync_is_recorded() {
+    let harness = Harness::start().await;
+    harness
+        .fake
+        .fail("/changes/startPageToken", 400, "badRequest", 1);
+
+    let bernard = harness.bernard().await;
+    assert!(bernard.sync_drive(DRIVE_ID).await.is_err());
+    bernard.sync_drive(DRIVE_ID).await.unwrap();
+
+    let runs = bernard.sync_history(DRIVE_ID).await.unwrap();
+    let errors: Vec<Option<ErrorKind>> = runs.iter().map(|run| run.error).collect();
+    assert_eq!(errors, [None, Some(ErrorKind::Network)]);
+    assert_eq!(runs[1].kind, RunKind::Full);
+    assert_eq!(runs[1].new_page_token, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_failing_before_it_starts_is_recorded() {
+    let harness = Harness::start().await;
+    let bernard = harness.bernard().await;
+
+    // Break the database behind Bernard's back, so looking up the drive fails.
+    let path = harness.database_path();
+    let pool = sqlx::SqlitePool::connect(path.to_str().unwrap())
+        .await
+        .unwrap();
+    sqlx::query("DROP TABLE drives")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+
+    let error = bernard.sync_drive(DRIVE_ID).await.err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::Database);
+
+    let runs = bernard.sync_history(DRIVE_ID).await.unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].kind, RunKind::Full);
+    assert_eq!(runs[0].error, Some(ErrorKind::Database));
+    assert!(harness.fake.requests().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_returns_a_report() {
+    let harness = Harness::start().await;
+    let fake = &harness.fake;
+    fake.upsert(DRIVE_ID, Item::folder("folder", "Folder", DRIVE_ID));
+    fake.upsert(DRIVE_ID, Item::file("orphan", "orphan.txt", "missing"));
+    fake.rate_limit("/files", None, 1);
+
+    let bernard = harness.bernard().await;
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    let report = kind.report();
+
+    assert!(matches!(kind, SyncKind::Full(_)));
+    assert_eq!(report.listed, 2);
+    assert!(report.orphaned_folders.is_empty());
+    assert_eq!(report.orphaned_files, ["orphan"]);
+    assert_eq!(report.folders.created, 1);
+    assert_eq!(report.files, ItemCounts::default());
+
+    // The token request does not count towards the Google Drive API.
+    let api_requests = fake
+        .requests()
+        .iter()
+        .filter(|request| !request.starts_with("/token"))
+        .count();
+    assert_eq!(report.requests, api_requests);
+    assert_eq!(report.retries, 1);
+    assert!(report.fetch_duration > Duration::default());
+    assert!(report.write_duration > Duration::default());
+
+    fake.upsert(DRIVE_ID, Item::file("file", "file.txt", "folder"));
+    let kind = bernard.sync_drive(DRIVE_ID).await.unwrap();
+    let report = kind.report();
+
+    assert!(matches!(kind, SyncKind::Partial(_)));
+    assert_eq!(report.listed, 1);
+    assert_eq!(report.files.created, 1);
+    assert_eq!(report.requests, 1);
+    assert_eq!(report.retries, 0);
+}